@@ -0,0 +1,75 @@
+// Renders the built-in scenes that build a BVH with the SAH builder and with
+// `--bvh median`, which splits like the original builder, and compares the
+// wall clock time, scene construction included. The other built-in scenes
+// intersect a flat list of objects and would time the same render twice.
+//
+//     cargo bench --bench bvh
+//     cargo bench --bench bvh -- -w 400 -n 16
+//
+// Extra arguments are passed on to the renderer.
+
+use std::{
+    env,
+    process::{Command, Stdio},
+    time::{Duration, Instant},
+};
+
+const RUNS: usize = 3;
+const SCENES: [&str; 1] = ["final_scene"];
+
+fn renderer() -> Command {
+    Command::new(env!("CARGO_BIN_EXE_raytracer-rs"))
+}
+
+fn render(scene: &str, bvh: &str, extra: &[String]) -> Duration {
+    let mut best = Duration::MAX;
+
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        let status = renderer()
+            .args(["--scene", scene, "--bvh", bvh])
+            .args(extra)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .expect("failed to run the renderer");
+        assert!(status.success(), "rendering {} failed", scene);
+        best = best.min(start.elapsed());
+    }
+
+    best
+}
+
+fn main() {
+    // `cargo bench` adds `--bench`
+    let mut extra: Vec<String> =
+        env::args().skip(1).filter(|arg| arg != "--bench").collect();
+    // a small render unless the arguments say otherwise, the renderer
+    // rejects a flag given twice
+    for (short, long, value) in
+        [("-w", "--width", "200"), ("-n", "--samples", "8")]
+    {
+        if !extra
+            .iter()
+            .any(|arg| arg == short || arg.starts_with(long))
+        {
+            extra.extend([short.to_string(), value.to_string()]);
+        }
+    }
+
+    println!(
+        "{:<20} {:>12} {:>12} {:>8}",
+        "scene", "original (s)", "sah (s)", "speedup"
+    );
+    for scene in SCENES {
+        let original = render(scene, "median", &extra).as_secs_f64();
+        let sah = render(scene, "sah", &extra).as_secs_f64();
+        println!(
+            "{:<20} {:>12.3} {:>12.3} {:>7.2}x",
+            scene,
+            original,
+            sah,
+            original / sah
+        );
+    }
+}
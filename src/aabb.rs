@@ -1,5 +1,9 @@
 use crate::{ray::Ray, vec3::Point3};
 
+// bound on the relative error of three rounded operations
+const GAMMA_3: f64 = 3.0 * f64::EPSILON / (1.0 - 3.0 * f64::EPSILON);
+
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Copy, Clone)]
 pub struct AABB {
     min: Point3,
@@ -19,24 +23,53 @@ impl AABB {
         self.min
     }
 
-    pub fn hit(&self, ray: &Ray, mut t_min: f64, mut t_max: f64) -> bool {
+    pub fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+        self.intersect(ray, t_min, t_max).is_some()
+    }
+
+    // Slab test, returns the part of [t_min, t_max] the ray spends inside the
+    // box
+    pub fn intersect(
+        &self,
+        ray: &Ray,
+        mut t_min: f64,
+        mut t_max: f64,
+    ) -> Option<(f64, f64)> {
+        let origin = ray.origin();
+        let direction = ray.direction();
+        let inv_direction = ray.inverse_direction();
+
         for a in 0..3 {
-            let inv_d = ray.inverse_direction()[a];
-            let mut t0 = (self.min[a] - ray.origin()[a]) * inv_d;
-            let mut t1 = (self.max[a] - ray.origin()[a]) * inv_d;
+            // a ray parallel to the slab is either inside it for every t or
+            // never, and the products below would be 0 * inf = NaN for an
+            // origin on one of its planes
+            if direction[a] == 0.0 || inv_direction[a].is_infinite() {
+                if origin[a] < self.min[a] || origin[a] > self.max[a] {
+                    return None;
+                }
+                continue;
+            }
 
-            if inv_d < 0.0 {
+            let mut t0 = (self.min[a] - origin[a]) * inv_direction[a];
+            let mut t1 = (self.max[a] - origin[a]) * inv_direction[a];
+            // only NaN in the ray or the box gets here
+            if t0.is_nan() || t1.is_nan() {
+                return None;
+            }
+            if t0 > t1 {
                 (t0, t1) = (t1, t0);
-            };
+            }
+            // rounding can put the exit of a grazing ray just before its entry
+            t1 *= 1.0 + 2.0 * GAMMA_3;
 
-            t_min = if t0 > t_min { t0 } else { t_min };
-            t_max = if t1 > t_max { t1 } else { t_max };
-            if t_max <= t_min {
-                return false;
+            t_min = t_min.max(t0);
+            t_max = t_max.min(t1);
+            if t_max < t_min {
+                return None;
             }
         }
 
-        true
+        Some((t_min, t_max))
     }
 
     pub fn surrounding_box(&self, other: &AABB) -> AABB {
@@ -47,11 +80,141 @@ impl AABB {
         );
 
         let max = Point3::new(
-            self.min.x().min(other.min.x()),
-            self.min.y().min(other.min.y()),
-            self.min.z().min(other.min.z()),
+            self.max.x().max(other.max.x()),
+            self.max.y().max(other.max.y()),
+            self.max.z().max(other.max.z()),
         );
 
         AABB { min, max }
     }
+
+    pub fn surface_area(&self) -> f64 {
+        let d = self.max - self.min;
+        2.0 * (d.x() * d.y() + d.y() * d.z() + d.z() * d.x())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::vec3::Vec3;
+
+    fn unit_box() -> AABB {
+        AABB::new(Point3::ZERO, Point3::ONE)
+    }
+
+    fn point() -> impl Strategy<Value = Point3> {
+        (-10.0..10.0, -10.0..10.0, -10.0..10.0)
+            .prop_map(|(x, y, z)| Point3::new(x, y, z))
+    }
+
+    #[test]
+    fn returns_entry_and_exit() {
+        let ray = Ray::new(Point3::new(-1.0, 0.5, 0.5), Vec3::from_x(1.0), 0.0);
+        assert_eq!(
+            unit_box()
+                .intersect(&ray, 0.0, f64::INFINITY)
+                .map(|(t0, t1)| (t0, t1.round())),
+            Some((1.0, 2.0))
+        );
+    }
+
+    #[test]
+    fn clips_to_the_ray_interval() {
+        let ray = Ray::new(Point3::new(-1.0, 0.5, 0.5), Vec3::from_x(1.0), 0.0);
+        assert!(unit_box().intersect(&ray, 0.0, 0.5).is_none());
+        assert!(unit_box().intersect(&ray, 2.5, 3.0).is_none());
+        let (t0, t1) = unit_box().intersect(&ray, 1.5, 1.75).unwrap();
+        assert_eq!((t0, t1), (1.5, 1.75));
+    }
+
+    #[test]
+    fn handles_axis_parallel_rays() {
+        // zero direction components give infinite inverse components
+        let inside =
+            Ray::new(Point3::new(0.5, 0.5, -1.0), Vec3::from_z(1.0), 0.0);
+        assert!(unit_box().hit(&inside, 0.0, f64::INFINITY));
+
+        let outside =
+            Ray::new(Point3::new(1.5, 0.5, -1.0), Vec3::from_z(1.0), 0.0);
+        assert!(!unit_box().hit(&outside, 0.0, f64::INFINITY));
+
+        // origin on the slab planes, where 0 * inf = NaN
+        let on_face =
+            Ray::new(Point3::new(0.0, 1.0, -1.0), Vec3::from_z(1.0), 0.0);
+        assert!(unit_box().hit(&on_face, 0.0, f64::INFINITY));
+    }
+
+    #[test]
+    fn handles_flat_boxes() {
+        let flat = AABB::new(Point3::ZERO, Point3::new(1.0, 1.0, 0.0));
+        let ray = Ray::new(Point3::new(0.5, 0.5, -1.0), Vec3::from_z(1.0), 0.0);
+        assert!(flat.hit(&ray, 0.0, f64::INFINITY));
+    }
+
+    #[test]
+    fn misses_with_nan_rays() {
+        let ray = Ray::new(Point3::ZERO, Vec3::from_float(f64::NAN), 0.0);
+        assert!(!unit_box().hit(&ray, 0.0, f64::INFINITY));
+
+        let ray = Ray::new(Point3::from_float(f64::NAN), Vec3::ONE, 0.0);
+        assert!(!unit_box().hit(&ray, 0.0, f64::INFINITY));
+    }
+
+    #[test]
+    fn surrounding_box_contains_both() {
+        let a =
+            AABB::new(Point3::new(-1.0, 0.0, 2.0), Point3::new(0.0, 1.0, 3.0));
+        let b =
+            AABB::new(Point3::new(0.5, -2.0, 0.0), Point3::new(4.0, 0.5, 1.0));
+        let c = a.surrounding_box(&b);
+        assert_eq!(c.min()[0], -1.0);
+        assert_eq!(c.min()[1], -2.0);
+        assert_eq!(c.min()[2], 0.0);
+        assert_eq!(c.max()[0], 4.0);
+        assert_eq!(c.max()[1], 1.0);
+        assert_eq!(c.max()[2], 3.0);
+    }
+
+    proptest! {
+        #[test]
+        fn rays_through_inner_points_hit(
+            corner in point(),
+            size in (0.0..5.0, 0.0..5.0, 0.0..5.0),
+            fraction in (0.0..=1.0, 0.0..=1.0, 0.0..=1.0),
+            origin in point(),
+        ) {
+            let size = Vec3::new(size.0, size.1, size.2);
+            let aabb = AABB::new(corner, corner + size);
+            let target =
+                corner + Vec3::new(fraction.0, fraction.1, fraction.2) * size;
+            // the ray reaches the target at t = 1
+            let ray = Ray::new(origin, target - origin, 0.0);
+
+            let (t0, t1) = aabb.intersect(&ray, 0.0, f64::INFINITY).unwrap();
+            prop_assert!(t0 <= 1.0 + 1e-9 && 1.0 - 1e-9 <= t1);
+        }
+
+        #[test]
+        fn interval_lies_inside_the_box(
+            corner in point(),
+            size in (0.01..5.0, 0.01..5.0, 0.01..5.0),
+            origin in point(),
+            direction in point(),
+        ) {
+            let max = corner + Vec3::new(size.0, size.1, size.2);
+            let aabb = AABB::new(corner, max);
+            let ray = Ray::new(origin, direction, 0.0);
+
+            if let Some((t0, t1)) = aabb.intersect(&ray, 0.0, f64::INFINITY) {
+                prop_assert!(t0 <= t1);
+                let p = ray.at(0.5 * (t0 + t1));
+                for a in 0..3 {
+                    prop_assert!(corner[a] - 1e-6 <= p[a] && p[a] <= max[a] + 1e-6);
+                }
+            }
+        }
+    }
 }
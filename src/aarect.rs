@@ -1,12 +1,16 @@
 use std::sync::Arc;
 
+use rand::Rng;
+
 use crate::{
     aabb::AABB,
     hit::{HitRecord, Hittable},
     material::Material,
+    ray::Ray,
     vec3::{Point3, Vec3},
 };
 
+#[derive(Clone)]
 pub struct XYRect {
     material: Arc<dyn Material>,
     x0: f64,
@@ -64,6 +68,8 @@ impl Hittable for XYRect {
             u: (x - self.x0) / (self.x1 - self.x0),
             v: (y - self.y0) / (self.y1 - self.y0),
             front_face: false,
+            outside_index_of_refraction: 1.0,
+            vertex_color: None,
         };
 
         let outward_normal = Vec3::from_z(1.0);
@@ -73,13 +79,36 @@ impl Hittable for XYRect {
     }
 
     fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<AABB> {
-        return Some(AABB::new(
+        Some(AABB::new(
             Point3::new(self.x0, self.y0, self.k - 0.0001),
             Point3::new(self.x1, self.y1, self.k + 0.0001),
-        ));
+        ))
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        let area = (self.x1 - self.x0) * (self.y1 - self.y0);
+        match self.hit(
+            &Ray::new(*origin, *direction, 0.0),
+            0.001,
+            f64::INFINITY,
+        ) {
+            Some(rec) => solid_angle_pdf(&rec, direction, area),
+            None => 0.0,
+        }
+    }
+
+    fn random(&self, origin: &Point3) -> Vec3 {
+        let mut rng = rand::thread_rng();
+        let on_light = Point3::new(
+            self.x0 + rng.gen::<f64>() * (self.x1 - self.x0),
+            self.y0 + rng.gen::<f64>() * (self.y1 - self.y0),
+            self.k,
+        );
+        on_light - *origin
     }
 }
 
+#[derive(Clone)]
 pub struct XZRect {
     material: Arc<dyn Material>,
     x0: f64,
@@ -137,6 +166,8 @@ impl Hittable for XZRect {
             u: (x - self.x0) / (self.x1 - self.x0),
             v: (z - self.z0) / (self.z1 - self.z0),
             front_face: false,
+            outside_index_of_refraction: 1.0,
+            vertex_color: None,
         };
 
         let outward_normal = Vec3::from_y(1.0);
@@ -146,13 +177,36 @@ impl Hittable for XZRect {
     }
 
     fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<AABB> {
-        return Some(AABB::new(
+        Some(AABB::new(
             Point3::new(self.x0, self.k - 0.0001, self.z0),
             Point3::new(self.x1, self.k + 0.0001, self.z1),
-        ));
+        ))
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        let area = (self.x1 - self.x0) * (self.z1 - self.z0);
+        match self.hit(
+            &Ray::new(*origin, *direction, 0.0),
+            0.001,
+            f64::INFINITY,
+        ) {
+            Some(rec) => solid_angle_pdf(&rec, direction, area),
+            None => 0.0,
+        }
+    }
+
+    fn random(&self, origin: &Point3) -> Vec3 {
+        let mut rng = rand::thread_rng();
+        let on_light = Point3::new(
+            self.x0 + rng.gen::<f64>() * (self.x1 - self.x0),
+            self.k,
+            self.z0 + rng.gen::<f64>() * (self.z1 - self.z0),
+        );
+        on_light - *origin
     }
 }
 
+#[derive(Clone)]
 pub struct YZRect {
     material: Arc<dyn Material>,
     y0: f64,
@@ -210,6 +264,8 @@ impl Hittable for YZRect {
             u: (y - self.y0) / (self.y1 - self.y0),
             v: (z - self.z0) / (self.z1 - self.z0),
             front_face: false,
+            outside_index_of_refraction: 1.0,
+            vertex_color: None,
         };
 
         let outward_normal = Vec3::from_x(1.0);
@@ -219,9 +275,42 @@ impl Hittable for YZRect {
     }
 
     fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<AABB> {
-        return Some(AABB::new(
+        Some(AABB::new(
             Point3::new(self.k - 0.0001, self.y0, self.z0),
             Point3::new(self.k + 0.0001, self.y1, self.z1),
-        ));
+        ))
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        let area = (self.y1 - self.y0) * (self.z1 - self.z0);
+        match self.hit(
+            &Ray::new(*origin, *direction, 0.0),
+            0.001,
+            f64::INFINITY,
+        ) {
+            Some(rec) => solid_angle_pdf(&rec, direction, area),
+            None => 0.0,
+        }
+    }
+
+    fn random(&self, origin: &Point3) -> Vec3 {
+        let mut rng = rand::thread_rng();
+        let on_light = Point3::new(
+            self.k,
+            self.y0 + rng.gen::<f64>() * (self.y1 - self.y0),
+            self.z0 + rng.gen::<f64>() * (self.z1 - self.z0),
+        );
+        on_light - *origin
+    }
+}
+
+// Turns the uniform density 1 / area over the rect into a density over
+// directions seen from the ray origin
+fn solid_angle_pdf(rec: &HitRecord, direction: &Vec3, area: f64) -> f64 {
+    let distance_squared = rec.t * rec.t * direction.length_squared();
+    let cosine = (direction.dot(rec.normal) / direction.length()).abs();
+    if cosine <= 0.0 || area <= 0.0 {
+        return 0.0;
     }
+    distance_squared / (cosine * area)
 }
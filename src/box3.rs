@@ -16,57 +16,56 @@ pub struct Box3 {
 
 impl Box3 {
     pub fn new(min: Point3, max: Point3, material: Arc<dyn Material>) -> Self {
-        let mut sides = World::new();
-        sides.push(Box::new(XYRect::new(
-            min.x(),
-            max.x(),
-            min.y(),
-            max.y(),
-            max.z(),
-            material.clone(),
-        )));
-        sides.push(Box::new(XYRect::new(
-            min.x(),
-            max.x(),
-            min.y(),
-            max.y(),
-            min.z(),
-            material.clone(),
-        )));
-
-        sides.push(Box::new(XZRect::new(
-            min.x(),
-            max.x(),
-            min.z(),
-            max.z(),
-            max.y(),
-            material.clone(),
-        )));
-        sides.push(Box::new(XZRect::new(
-            min.x(),
-            max.x(),
-            min.z(),
-            max.z(),
-            min.y(),
-            material.clone(),
-        )));
-
-        sides.push(Box::new(YZRect::new(
-            min.y(),
-            max.y(),
-            min.z(),
-            max.z(),
-            max.x(),
-            material.clone(),
-        )));
-        sides.push(Box::new(YZRect::new(
-            min.y(),
-            max.y(),
-            min.z(),
-            max.z(),
-            min.x(),
-            material.clone(),
-        )));
+        let sides: World = vec![
+            Box::new(XYRect::new(
+                min.x(),
+                max.x(),
+                min.y(),
+                max.y(),
+                max.z(),
+                material.clone(),
+            )),
+            Box::new(XYRect::new(
+                min.x(),
+                max.x(),
+                min.y(),
+                max.y(),
+                min.z(),
+                material.clone(),
+            )),
+            Box::new(XZRect::new(
+                min.x(),
+                max.x(),
+                min.z(),
+                max.z(),
+                max.y(),
+                material.clone(),
+            )),
+            Box::new(XZRect::new(
+                min.x(),
+                max.x(),
+                min.z(),
+                max.z(),
+                min.y(),
+                material.clone(),
+            )),
+            Box::new(YZRect::new(
+                min.y(),
+                max.y(),
+                min.z(),
+                max.z(),
+                max.x(),
+                material.clone(),
+            )),
+            Box::new(YZRect::new(
+                min.y(),
+                max.y(),
+                min.z(),
+                max.z(),
+                min.x(),
+                material.clone(),
+            )),
+        ];
 
         Box3 { min, max, sides }
     }
@@ -1,180 +1,546 @@
-use std::{cmp::Ordering, process::exit, sync::Arc};
+use std::{cell::Cell, cmp::Ordering, ops};
 
 use rand::Rng;
 
-use crate::{aabb::AABB, hit::Hittable, vec3::Vec3};
+use crate::{
+    aabb::AABB,
+    hit::{HitRecord, Hittable, World},
+    ray::Ray,
+    vec3::Point3,
+};
 
-pub struct BvhNode {
-    left: Option<Box<dyn Hittable>>,
-    right: Option<Box<dyn Hittable>>,
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum SplitMethod {
+    // binned surface area heuristic
+    #[default]
+    Sah,
+    // the splits of the original builder: halves at the median along x or
+    // y, picked at random, down to one primitive per leaf
+    Median,
+}
+
+const SAH_BINS: usize = 16;
+const MAX_LEAF_SIZE: usize = 4;
+// cost of visiting a node relative to intersecting a primitive
+const TRAVERSAL_COST: f64 = 0.5;
+// deeper subtrees are cut into leaves, which bounds the traversal stack
+const MAX_DEPTH: usize = 64;
+
+#[derive(Clone, Copy, Default, Debug)]
+pub struct TraversalStats {
+    pub rays: u64,
+    pub nodes_visited: u64,
+    pub primitives_tested: u64,
+}
+
+impl ops::AddAssign for TraversalStats {
+    fn add_assign(&mut self, other: Self) {
+        self.rays += other.rays;
+        self.nodes_visited += other.nodes_visited;
+        self.primitives_tested += other.primitives_tested;
+    }
+}
+
+thread_local! {
+    // only counting inside `count_stats`
+    static STATS: Cell<Option<TraversalStats>> = const { Cell::new(None) };
+}
+
+// Runs `f` and returns the BVH work it did on the calling thread
+pub(crate) fn count_stats<T>(f: impl FnOnce() -> T) -> (T, TraversalStats) {
+    let outer =
+        STATS.with(|cell| cell.replace(Some(TraversalStats::default())));
+    let result = f();
+    let stats = STATS.with(|cell| cell.replace(outer)).unwrap_or_default();
+    (result, stats)
+}
+
+fn record(stats: TraversalStats) {
+    STATS.with(|cell| {
+        if let Some(mut total) = cell.get() {
+            total += stats;
+            cell.set(Some(total));
+        }
+    });
+}
+
+// Counts a ray cast into the scene, the denominator of the per ray averages
+pub(crate) fn record_ray() {
+    record(TraversalStats {
+        rays: 1,
+        ..Default::default()
+    });
+}
+
+enum LinearNode {
+    Leaf {
+        bounding_box: AABB,
+        first: usize,
+        count: usize,
+    },
+    // the first child directly follows its parent
+    Interior {
+        bounding_box: AABB,
+        second_child: usize,
+        axis: usize,
+    },
+}
+
+// Nodes are stored depth first in one array, with the primitives of each leaf
+// in a contiguous range
+pub struct Bvh {
+    nodes: Vec<LinearNode>,
+    primitives: World,
+}
+
+struct Primitive {
+    object: Box<dyn Hittable>,
     bounding_box: AABB,
+    centroid: Point3,
 }
 
-impl BvhNode {
-    pub fn new(
-        mut src_objects: Vec<Box<dyn Hittable>>,
+enum Split {
+    Leaf(Vec<Primitive>),
+    Interior(usize, Vec<Primitive>, Vec<Primitive>),
+}
+
+impl Bvh {
+    pub fn new(src_objects: World, time0: f64, time1: f64) -> Self {
+        Self::with_split_method(src_objects, time0, time1, SplitMethod::Sah)
+    }
+
+    pub fn with_split_method(
+        src_objects: World,
         time0: f64,
         time1: f64,
+        method: SplitMethod,
     ) -> Self {
-        let axis: i32 = rand::thread_rng().gen_range(0..2);
+        assert!(!src_objects.is_empty(), "a bvh needs at least one object");
+
+        let primitives: Vec<Primitive> = src_objects
+            .into_iter()
+            .map(|object| {
+                let bounding_box = object
+                    .bounding_box(time0, time1)
+                    .expect("bvh objects need a bounding box");
+                Primitive {
+                    object,
+                    bounding_box,
+                    centroid: 0.5 * (bounding_box.min() + bounding_box.max()),
+                }
+            })
+            .collect();
 
-        let comparator = match axis {
-            0 => Self::box_x_compare,
-            1 => Self::box_y_compare,
-            2 => Self::box_z_compare,
-            _ => Self::box_x_compare,
+        let mut bvh = Self {
+            nodes: Vec::with_capacity(2 * primitives.len()),
+            primitives: Vec::with_capacity(primitives.len()),
         };
+        bvh.build(primitives, method, 0);
+        bvh
+    }
 
-        let (left, right) = match src_objects.len() {
-            1 => {
-                let item = src_objects.remove(0);
-                (Some(item), None)
-            }
-            2 if comparator(&src_objects[0], &src_objects[1])
-                == Ordering::Greater =>
-            {
-                (Some(src_objects.remove(0)), Some(src_objects.remove(0)))
-            }
-            2 => (Some(src_objects.remove(1)), Some(src_objects.remove(0))),
-            _ => {
-                src_objects.sort_by(comparator);
-                let others = src_objects.split_off(src_objects.len() / 2);
-                let left: Box<dyn Hittable> =
-                    Box::new(BvhNode::new(src_objects, time0, time1));
-                let right: Box<dyn Hittable> =
-                    Box::new(BvhNode::new(others, time0, time1));
-                (Some(left), Some(right))
+    fn build(
+        &mut self,
+        primitives: Vec<Primitive>,
+        method: SplitMethod,
+        depth: usize,
+    ) {
+        let bounding_box = enclosing_box(&primitives);
+
+        let split = if depth >= MAX_DEPTH {
+            Split::Leaf(primitives)
+        } else {
+            match method {
+                SplitMethod::Sah => sah_split(primitives, &bounding_box),
+                SplitMethod::Median => median_split(primitives),
             }
         };
 
-        // let elem = match object_span {
-        //     1 => object_start,
-        //     2 if comparator(object_start, object_start_next) => object_start,
-        //     2 => object_start,
-        //     _ => {
-        //         // objects.sort_by(comparator);
-
-        //         let mid = start + object_span / 2;
-
-        //         // let left: Box<dyn Hittable> =
-        //         // let right: Box<dyn Hittable> =
-        //         //     ;
-        //         let x: Box<dyn Hittable> =
-        //             Box::new(BvhNode::new(src_objects, start, mid, time0, time1));
-        //         &x
-        //     }
-        // };
-        match (left, right) {
-            (Some(left), Some(right)) => match (
-                left.bounding_box(time0, time1),
-                right.bounding_box(time0, time1),
-            ) {
-                (Some(left_box), Some(right_box)) => Self {
-                    left: Some(left),
-                    right: Some(right),
-                    bounding_box: left_box.surrounding_box(&right_box),
-                },
-                _ => Self {
-                    left: None,
-                    right: None,
-                    bounding_box: AABB::new(Vec3::ZERO, Vec3::ONE),
-                },
-            },
-            _ => Self {
-                left: None,
-                right: None,
-                bounding_box: AABB::new(Vec3::ZERO, Vec3::ONE),
-            },
-        }
+        match split {
+            Split::Leaf(primitives) => {
+                self.nodes.push(LinearNode::Leaf {
+                    bounding_box,
+                    first: self.primitives.len(),
+                    count: primitives.len(),
+                });
+                self.primitives
+                    .extend(primitives.into_iter().map(|p| p.object));
+            }
+            Split::Interior(axis, left, right) => {
+                let index = self.nodes.len();
+                self.nodes.push(LinearNode::Interior {
+                    bounding_box,
+                    second_child: 0,
+                    axis,
+                });
 
-        // match (
-        //     left.map(|b| b.bounding_box(time0, time1)),
-        //     right.map(|b| b.bounding_box(time0, time1)),
-        // ) {
-        //     (Some(Some(left_box)), Some(Some(right_box))) => Self {
-        //         left,
-        //         right,
-        //         bounding_box: left_box.surrounding_box(&right_box),
-        //     },
-        //     _ => {
-        //         eprintln!("porcodio");
-        //         exit(0)
-        //     }
-        // }
+                self.build(left, method, depth + 1);
+                let second = self.nodes.len();
+                self.build(right, method, depth + 1);
+
+                if let LinearNode::Interior { second_child, .. } =
+                    &mut self.nodes[index]
+                {
+                    *second_child = second;
+                }
+            }
+        }
     }
+}
 
-    fn left(&self) -> &Option<Box<dyn Hittable>> {
-        &self.left
+fn sah_split(mut primitives: Vec<Primitive>, bounding_box: &AABB) -> Split {
+    let count = primitives.len();
+    if count == 1 {
+        return Split::Leaf(primitives);
     }
 
-    fn right(&self) -> &Option<Box<dyn Hittable>> {
-        &self.right
+    let (centroid_min, centroid_max) = primitives.iter().fold(
+        (
+            Point3::from_float(f64::INFINITY),
+            Point3::from_float(f64::NEG_INFINITY),
+        ),
+        |(mut min, mut max), p| {
+            for a in 0..3 {
+                min[a] = min[a].min(p.centroid[a]);
+                max[a] = max[a].max(p.centroid[a]);
+            }
+            (min, max)
+        },
+    );
+
+    let bin_of = |p: &Primitive, axis: usize| {
+        let extent = centroid_max[axis] - centroid_min[axis];
+        let offset = (p.centroid[axis] - centroid_min[axis]) / extent;
+        ((offset * SAH_BINS as f64) as usize).min(SAH_BINS - 1)
+    };
+
+    // (cost, axis, bin the right side starts at)
+    let mut best: Option<(f64, usize, usize)> = None;
+    for axis in 0..3 {
+        if centroid_max[axis] <= centroid_min[axis] {
+            continue;
+        }
+
+        let mut counts = [0; SAH_BINS];
+        let mut boxes: [Option<AABB>; SAH_BINS] = [None; SAH_BINS];
+        for p in &primitives {
+            let bin = bin_of(p, axis);
+            counts[bin] += 1;
+            boxes[bin] = Some(union(boxes[bin], &p.bounding_box));
+        }
+
+        // area times count of everything right of each split, swept from
+        // the right, then the left side while sweeping back
+        let mut right_cost = [0.0; SAH_BINS];
+        let mut right_box = None;
+        let mut right_count = 0;
+        for bin in (1..SAH_BINS).rev() {
+            right_count += counts[bin];
+            if let Some(b) = &boxes[bin] {
+                right_box = Some(union(right_box, b));
+            }
+            right_cost[bin] = right_count as f64
+                * right_box.map_or(0.0, |b| b.surface_area());
+        }
+
+        let mut left_box = None;
+        let mut left_count = 0;
+        for bin in 1..SAH_BINS {
+            left_count += counts[bin - 1];
+            if let Some(b) = &boxes[bin - 1] {
+                left_box = Some(union(left_box, b));
+            }
+            if left_count == 0 || left_count == count {
+                continue;
+            }
+
+            let cost = left_count as f64
+                * left_box.map_or(0.0, |b| b.surface_area())
+                + right_cost[bin];
+            if best.is_none_or(|(best_cost, _, _)| cost < best_cost) {
+                best = Some((cost, axis, bin));
+            }
+        }
     }
 
-    fn bounding_box(&self) -> &AABB {
-        &self.bounding_box
+    let area = bounding_box.surface_area();
+    let split = best.map(|(cost, axis, bin)| {
+        let cost = if area > 0.0 {
+            TRAVERSAL_COST + cost / area
+        } else {
+            TRAVERSAL_COST + count as f64
+        };
+        (cost, axis, bin)
+    });
+
+    match split {
+        Some((cost, _, _))
+            if count <= MAX_LEAF_SIZE && cost >= count as f64 =>
+        {
+            Split::Leaf(primitives)
+        }
+        Some((_, axis, bin)) => {
+            let (left, right) =
+                primitives.into_iter().partition(|p| bin_of(p, axis) < bin);
+            Split::Interior(axis, left, right)
+        }
+        // all centroids coincide, no plane separates them
+        None if count <= MAX_LEAF_SIZE => Split::Leaf(primitives),
+        None => {
+            let right = primitives.split_off(count / 2);
+            Split::Interior(0, primitives, right)
+        }
     }
 }
 
-impl Hittable for BvhNode {
-    fn hit(
-        &self,
-        ray: &crate::ray::Ray,
-        t_min: f64,
-        t_max: f64,
-    ) -> Option<crate::hit::HitRecord> {
-        if !self.bounding_box.hit(ray, t_min, t_max) {
-            return None;
-        }
+fn median_split(mut primitives: Vec<Primitive>) -> Split {
+    if primitives.len() == 1 {
+        return Split::Leaf(primitives);
+    }
 
-        let rec_left = self.left.as_ref().map(|b| b.hit(ray, t_min, t_max));
+    // z was never picked
+    let axis = rand::thread_rng().gen_range(0..2);
+    primitives.sort_by(|a, b| {
+        a.bounding_box.min()[axis]
+            .partial_cmp(&b.bounding_box.min()[axis])
+            .unwrap_or(Ordering::Equal)
+    });
+    let right = primitives.split_off(primitives.len() / 2);
+    Split::Interior(axis, primitives, right)
+}
 
-        let right_t_max = match &rec_left {
-            Some(Some(rec)) => rec.t,
-            _ => t_max,
-        };
+impl Hittable for Bvh {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let direction = ray.direction();
+        let negative = [
+            direction.x() < 0.0,
+            direction.y() < 0.0,
+            direction.z() < 0.0,
+        ];
 
-        let rec_right =
-            self.right.as_ref().map(|b| b.hit(ray, t_min, right_t_max));
+        let mut stats = TraversalStats::default();
+        let mut closest = t_max;
+        let mut rec = None;
 
-        match (rec_left, rec_right) {
-            (_, Some(Some(rec))) => Some(rec),
-            (Some(Some(rec)), _) => Some(rec),
-            _ => None,
+        let mut stack = [0; MAX_DEPTH + 1];
+        let mut stack_len = 0;
+        let mut index = 0;
+        loop {
+            stats.nodes_visited += 1;
+            let next = match &self.nodes[index] {
+                LinearNode::Leaf {
+                    bounding_box,
+                    first,
+                    count,
+                } => {
+                    if bounding_box.hit(ray, t_min, closest) {
+                        for object in &self.primitives[*first..first + count] {
+                            stats.primitives_tested += 1;
+                            if let Some(hit) = object.hit(ray, t_min, closest) {
+                                closest = hit.t;
+                                rec = Some(hit);
+                            }
+                        }
+                    }
+                    None
+                }
+                LinearNode::Interior {
+                    bounding_box,
+                    second_child,
+                    axis,
+                } => {
+                    if bounding_box.hit(ray, t_min, closest) {
+                        // visit the child nearer to the ray origin first, its
+                        // hits shorten the search in the other one
+                        let (near, far) = if negative[*axis] {
+                            (*second_child, index + 1)
+                        } else {
+                            (index + 1, *second_child)
+                        };
+                        stack[stack_len] = far;
+                        stack_len += 1;
+                        Some(near)
+                    } else {
+                        None
+                    }
+                }
+            };
+
+            index = match next {
+                Some(next) => next,
+                None if stack_len > 0 => {
+                    stack_len -= 1;
+                    stack[stack_len]
+                }
+                None => break,
+            };
         }
+
+        record(stats);
+
+        rec
     }
 
     fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<AABB> {
-        Some(self.bounding_box)
+        match &self.nodes[0] {
+            LinearNode::Leaf { bounding_box, .. }
+            | LinearNode::Interior { bounding_box, .. } => Some(*bounding_box),
+        }
     }
 }
 
-impl BvhNode {
-    fn box_compare(
-        a: &Box<dyn Hittable>,
-        b: &Box<dyn Hittable>,
-        axis: usize,
-    ) -> Ordering {
-        let a_bounding_box = a.bounding_box(0.0, 0.0);
-        let b_bounding_box = b.bounding_box(0.0, 0.0);
+fn union(a: Option<AABB>, b: &AABB) -> AABB {
+    match a {
+        Some(a) => a.surrounding_box(b),
+        None => *b,
+    }
+}
+
+fn enclosing_box(primitives: &[Primitive]) -> AABB {
+    primitives
+        .iter()
+        .fold(None, |acc, p| Some(union(acc, &p.bounding_box)))
+        .expect("enclosing box of no primitives")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::{
+        material::{Lambertian, Material},
+        sphere::Sphere,
+        texture::SolidColor,
+        triangle::Triangle,
+        vec3::Color,
+    };
+
+    #[derive(Clone, Debug)]
+    enum Shape {
+        Sphere(Point3, f64),
+        Triangle(Point3, Point3, Point3),
+    }
+
+    fn point(range: f64) -> impl Strategy<Value = Point3> {
+        (-range..range, -range..range, -range..range)
+            .prop_map(|(x, y, z)| Point3::new(x, y, z))
+    }
 
-        match (a_bounding_box, b_bounding_box) {
-            (Some(box_a), Some(box_b)) => {
-                box_a.min()[axis].partial_cmp(&box_b.min()[axis]).unwrap()
+    fn shape() -> impl Strategy<Value = Shape> {
+        prop_oneof![
+            (point(10.0), 0.05..2.0).prop_map(|(c, r)| Shape::Sphere(c, r)),
+            (point(10.0), point(1.5), point(1.5))
+                .prop_map(|(v0, e1, e2)| Shape::Triangle(v0, v0 + e1, v0 + e2)),
+        ]
+    }
+
+    fn world(shapes: &[Shape]) -> World {
+        let material: Arc<dyn Material> =
+            Arc::new(Lambertian::new(Arc::new(SolidColor::new(Color::ONE))));
+
+        shapes
+            .iter()
+            .map(|shape| -> Box<dyn Hittable> {
+                match *shape {
+                    Shape::Sphere(center, radius) => {
+                        Box::new(Sphere::new(center, radius, material.clone()))
+                    }
+                    Shape::Triangle(v0, v1, v2) => {
+                        Box::new(Triangle::new(v0, v1, v2, material.clone()))
+                    }
+                }
+            })
+            .collect()
+    }
+
+    fn check(
+        shapes: &[Shape],
+        rays: &[(Point3, Point3)],
+        method: SplitMethod,
+    ) -> Result<(), TestCaseError> {
+        let brute_force = world(shapes);
+        let bvh = Bvh::with_split_method(world(shapes), 0.0, 1.0, method);
+
+        for &(origin, target) in rays {
+            let ray = Ray::new(origin, target - origin, 0.0);
+            let expected = brute_force.hit(&ray, 0.001, f64::INFINITY);
+            let actual = bvh.hit(&ray, 0.001, f64::INFINITY);
+
+            match (expected, actual) {
+                (None, None) => {}
+                (Some(expected), Some(actual)) => {
+                    prop_assert_eq!(expected.t, actual.t);
+                    prop_assert_eq!(expected.p, actual.p);
+                }
+                (expected, actual) => prop_assert!(
+                    false,
+                    "brute force hit at {:?}, bvh at {:?}",
+                    expected.map(|rec| rec.t),
+                    actual.map(|rec| rec.t)
+                ),
             }
-            _ => Ordering::Equal,
         }
+
+        Ok(())
     }
 
-    fn box_x_compare(a: &Box<dyn Hittable>, b: &Box<dyn Hittable>) -> Ordering {
-        Self::box_compare(a, b, 0)
+    proptest! {
+        #[test]
+        fn sah_matches_brute_force(
+            shapes in prop::collection::vec(shape(), 1..64),
+            rays in prop::collection::vec((point(15.0), point(10.0)), 32),
+        ) {
+            check(&shapes, &rays, SplitMethod::Sah)?;
+        }
+
+        #[test]
+        fn median_matches_brute_force(
+            shapes in prop::collection::vec(shape(), 1..64),
+            rays in prop::collection::vec((point(15.0), point(10.0)), 32),
+        ) {
+            check(&shapes, &rays, SplitMethod::Median)?;
+        }
     }
 
-    fn box_y_compare(a: &Box<dyn Hittable>, b: &Box<dyn Hittable>) -> Ordering {
-        Self::box_compare(a, b, 1)
+    #[test]
+    fn handles_coincident_centroids() {
+        let shapes = vec![Shape::Sphere(Point3::ZERO, 1.0); 100];
+        let rays = [
+            (Point3::from_z(-5.0), Point3::ZERO),
+            (Point3::new(5.0, 5.0, 5.0), Point3::from_x(0.5)),
+            (Point3::from_z(-5.0), Point3::from_x(3.0)),
+        ];
+        check(&shapes, &rays, SplitMethod::Sah).unwrap();
     }
 
-    fn box_z_compare(a: &Box<dyn Hittable>, b: &Box<dyn Hittable>) -> Ordering {
-        Self::box_compare(a, b, 2)
+    #[test]
+    fn hits_along_axis_parallel_rays() {
+        // grid of spheres, rays along the axes have infinite inverse
+        // direction components
+        let shapes: Vec<Shape> = (0..64)
+            .map(|i| {
+                let c = Point3::new(
+                    (i % 4) as f64,
+                    ((i / 4) % 4) as f64,
+                    (i / 16) as f64,
+                );
+                Shape::Sphere(2.0 * c, 0.5)
+            })
+            .collect();
+        let rays: Vec<(Point3, Point3)> = (0..4)
+            .flat_map(|i| {
+                let x = 2.0 * i as f64;
+                [
+                    (Point3::new(x, 2.0, -10.0), Point3::new(x, 2.0, 0.0)),
+                    (Point3::new(-10.0, x, 4.0), Point3::new(0.0, x, 4.0)),
+                    (Point3::new(2.0, 20.0, x), Point3::new(2.0, 0.0, x)),
+                ]
+            })
+            .collect();
+        check(&shapes, &rays, SplitMethod::Sah).unwrap();
+        check(&shapes, &rays, SplitMethod::Median).unwrap();
     }
 }
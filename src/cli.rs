@@ -0,0 +1,244 @@
+use std::{error::Error, path::PathBuf};
+
+use clap::{builder::PossibleValuesParser, Parser};
+
+use raytracer_rs::{
+    bvh::SplitMethod,
+    framebuffer::{ExrPrecision, OutputFormat},
+    gltf, scene_file,
+    scenes::Scene,
+    Integrator,
+};
+
+#[derive(Parser)]
+#[command(version, about = "A simple multithreaded raytracer")]
+pub struct Args {
+    /// Built-in scene to render
+    #[arg(
+        short,
+        long,
+        default_value = "final_scene",
+        value_parser = PossibleValuesParser::new(Scene::NAMES)
+    )]
+    pub scene: String,
+
+    /// Scene file to render instead of a built-in scene, TOML or glTF
+    /// (.gltf, .glb)
+    #[arg(short, long, conflicts_with = "scene")]
+    pub file: Option<PathBuf>,
+
+    /// Print the available scenes and exit
+    #[arg(long)]
+    pub list_scenes: bool,
+
+    /// Output image path, the format follows from the extension (png, jpg,
+    /// bmp, tga, ppm, or exr, hdr, pfm for linear radiance). A binary PPM is
+    /// written to stdout when omitted
+    #[arg(short, long, value_parser = parse_output)]
+    pub output: Option<PathBuf>,
+
+    /// Store OpenEXR output as 16 bit half floats instead of 32 bit floats
+    #[arg(long)]
+    pub exr_half: bool,
+
+    /// Image width in pixels, the height follows from the aspect ratio
+    #[arg(short = 'w', long, value_parser = clap::value_parser!(u64).range(2..))]
+    pub width: Option<u64>,
+
+    /// Aspect ratio, either as a number (1.5) or as a ratio (16:9)
+    #[arg(short, long, value_parser = parse_aspect_ratio)]
+    pub aspect_ratio: Option<f64>,
+
+    /// Samples per pixel
+    #[arg(short = 'n', long, value_parser = clap::value_parser!(u64).range(1..))]
+    pub samples: Option<u64>,
+
+    /// Maximum number of bounces per ray
+    #[arg(short = 'd', long, value_parser = clap::value_parser!(u64).range(1..))]
+    pub max_depth: Option<u64>,
+
+    /// Vertical field of view in degrees
+    #[arg(long)]
+    pub vfov: Option<f64>,
+
+    /// Camera aperture, 0 disables depth of field
+    #[arg(long)]
+    pub aperture: Option<f64>,
+
+    /// BVH builder, `median` splits like the original builder did, for
+    /// comparison. glTF files and triangle meshes always use `sah`
+    #[arg(
+        long,
+        default_value = "sah",
+        value_parser = PossibleValuesParser::new(["sah", "median"])
+    )]
+    pub bvh: String,
+
+    /// Print the BVH nodes visited and primitives tested per ray after
+    /// rendering
+    #[arg(long)]
+    pub bvh_stats: bool,
+
+    /// Path integrator, `bsdf` only samples materials and serves as a
+    /// reference for `mis`
+    #[arg(
+        long,
+        default_value = "mis",
+        value_parser = PossibleValuesParser::new(["mis", "bsdf"])
+    )]
+    pub integrator: String,
+
+    /// Trace wavelengths instead of red, green and blue, so that dielectrics
+    /// with a dispersive index of refraction split light into colors
+    #[arg(long)]
+    pub spectral: bool,
+}
+
+impl Args {
+    pub fn scene(&self) -> Result<Scene, Box<dyn Error>> {
+        let is_gltf = |path: &PathBuf| {
+            path.extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| {
+                    ext.eq_ignore_ascii_case("gltf")
+                        || ext.eq_ignore_ascii_case("glb")
+                })
+        };
+
+        let mut scene = match &self.file {
+            Some(path) if is_gltf(path) => gltf::load(path)?,
+            Some(path) => {
+                scene_file::load_with_split_method(path, self.split_method())?
+            }
+            None => Scene::from_name_with_split_method(
+                &self.scene,
+                self.split_method(),
+            )
+            .expect("scene name is validated by the parser"),
+        };
+
+        if let Some(aspect_ratio) = self.aspect_ratio {
+            scene.set_aspect_ratio(aspect_ratio);
+        }
+        if let Some(width) = self.width {
+            scene.set_image_width(width);
+        }
+        if let Some(samples) = self.samples {
+            scene.samples_per_pixel = samples;
+        }
+        if let Some(max_depth) = self.max_depth {
+            scene.max_depth = max_depth;
+        }
+        if let Some(vfov) = self.vfov {
+            scene.vfov = vfov;
+        }
+        if let Some(aperture) = self.aperture {
+            scene.aperture = aperture;
+        }
+
+        Ok(scene)
+    }
+
+    pub fn split_method(&self) -> SplitMethod {
+        match self.bvh.as_str() {
+            "median" => SplitMethod::Median,
+            _ => SplitMethod::Sah,
+        }
+    }
+
+    pub fn integrator(&self) -> Integrator {
+        match self.integrator.as_str() {
+            "bsdf" => Integrator::Bsdf,
+            _ => Integrator::Mis,
+        }
+    }
+
+    pub fn exr_precision(&self) -> ExrPrecision {
+        if self.exr_half {
+            ExrPrecision::Half
+        } else {
+            ExrPrecision::Float
+        }
+    }
+}
+
+fn parse_aspect_ratio(s: &str) -> Result<f64, String> {
+    let ratio = match s.split_once(':') {
+        Some((w, h)) => {
+            let w: f64 = w.trim().parse().map_err(|_| invalid_ratio(s))?;
+            let h: f64 = h.trim().parse().map_err(|_| invalid_ratio(s))?;
+            w / h
+        }
+        None => s.trim().parse().map_err(|_| invalid_ratio(s))?,
+    };
+
+    if ratio.is_finite() && ratio > 0.0 {
+        Ok(ratio)
+    } else {
+        Err(invalid_ratio(s))
+    }
+}
+
+fn invalid_ratio(s: &str) -> String {
+    format!("`{}` is not a positive aspect ratio", s)
+}
+
+fn parse_output(s: &str) -> Result<PathBuf, String> {
+    let path = PathBuf::from(s);
+    match OutputFormat::from_path(&path) {
+        Some(_) => Ok(path),
+        None => Err(format!(
+            "unsupported image format for `{}`, expected one of: {}",
+            s,
+            OutputFormat::ALL
+                .iter()
+                .flat_map(|format| format.extensions())
+                .copied()
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_aspect_ratios() {
+        assert_eq!(parse_aspect_ratio("16:9"), Ok(16.0 / 9.0));
+        assert_eq!(parse_aspect_ratio(" 4 : 3 "), Ok(4.0 / 3.0));
+        assert_eq!(parse_aspect_ratio("1.5"), Ok(1.5));
+
+        for s in [
+            "0", "-1.5", "1:0", "0:1", "-16:9", "16:", "16:9:1", "wide", "",
+        ] {
+            assert_eq!(
+                parse_aspect_ratio(s),
+                Err(format!("`{}` is not a positive aspect ratio", s))
+            );
+        }
+    }
+
+    #[test]
+    fn parses_outputs_with_a_known_extension() {
+        assert_eq!(parse_output("out.png"), Ok(PathBuf::from("out.png")));
+        assert_eq!(
+            parse_output("renders/out.EXR"),
+            Ok(PathBuf::from("renders/out.EXR"))
+        );
+
+        for s in ["out.gif", "out"] {
+            let message = parse_output(s).unwrap_err();
+            assert!(
+                message.starts_with(&format!(
+                    "unsupported image format for `{}`, expected one of: png",
+                    s
+                )),
+                "{}",
+                message
+            );
+            assert!(message.ends_with("pfm"), "{}", message);
+        }
+    }
+}
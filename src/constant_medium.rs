@@ -5,7 +5,7 @@ use rand::Rng;
 use crate::{
     hit::{HitRecord, Hittable},
     material::{Isotropic, Material},
-    texture::Texture,
+    texture::{SolidColor, Texture},
     vec3::{Color, Vec3},
 };
 
@@ -33,11 +33,7 @@ impl ConstantMedium {
         density: f64,
         color: Color,
     ) -> Self {
-        Self {
-            boundary,
-            neg_inv_density: -1.0 / density,
-            phase_function: Arc::new(Isotropic::from_color(color)),
-        }
+        Self::new(boundary, density, Arc::new(SolidColor::new(color)))
     }
 }
 
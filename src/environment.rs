@@ -0,0 +1,253 @@
+use std::{f64::consts::PI, path::Path};
+
+use image::{ImageResult, Rgb, Rgb32FImage};
+use rand::Rng;
+
+use crate::vec3::{Color, Vec3};
+
+// Light arriving from infinitely far away in every direction, seen by rays
+// that leave the scene
+pub trait Environment: Send + Sync {
+    // radiance arriving along -`direction`, which is a unit vector
+    fn radiance(&self, direction: &Vec3) -> Color;
+
+    // solid angle density of `random` picking `direction`
+    fn pdf_value(&self, direction: &Vec3) -> f64;
+
+    // unit direction towards the environment, ideally where it is bright
+    fn random(&self) -> Vec3;
+}
+
+// An equirectangular (latitude-longitude) image, +y is up and the middle of
+// the image looks down -z before rotating
+pub struct EnvironmentMap {
+    image: Rgb32FImage,
+    // radians around +y
+    rotation: f64,
+    intensity: f64,
+    // cumulative distributions of luminance times sin(theta), over the rows
+    // and over the pixels of each row
+    rows: Vec<f64>,
+    columns: Vec<Vec<f64>>,
+    // luminance weight of every pixel over their average, the density over
+    // the unit square of image coordinates
+    densities: Vec<f64>,
+}
+
+impl EnvironmentMap {
+    pub fn new(image: Rgb32FImage, rotation: f64, intensity: f64) -> Self {
+        let (width, height) = image.dimensions();
+        let (width, height) = (width as usize, height as usize);
+
+        let mut weights = vec![0.0; width * height];
+        for (x, y, pixel) in image.enumerate_pixels() {
+            let [r, g, b] = pixel.0.map(f64::from);
+            let luminance = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+            // rows near the poles cover less solid angle
+            let theta = PI * (y as f64 + 0.5) / height as f64;
+            weights[y as usize * width + x as usize] =
+                luminance.max(0.0) * theta.sin();
+        }
+
+        let total: f64 = weights.iter().sum();
+        let densities = if total > 0.0 {
+            let scale = (width * height) as f64 / total;
+            weights.iter().map(|w| w * scale).collect()
+        } else {
+            // black, sample uniformly over the image
+            vec![1.0; width * height]
+        };
+
+        let columns: Vec<Vec<f64>> =
+            densities.chunks(width).map(cumulative).collect();
+        let row_sums: Vec<f64> = columns
+            .iter()
+            .map(|row| row.last().copied().unwrap_or(0.0))
+            .collect();
+        let rows = cumulative(&row_sums);
+
+        Self {
+            image,
+            rotation: rotation.to_radians(),
+            intensity,
+            rows,
+            columns,
+            densities,
+        }
+    }
+
+    // Tabulates an analytic environment, e.g. to importance sample it
+    pub fn from_fn(
+        width: u32,
+        height: u32,
+        radiance: impl Fn(&Vec3) -> Color,
+    ) -> Self {
+        let image = Rgb32FImage::from_fn(width, height, |x, y| {
+            let u = (x as f64 + 0.5) / width as f64;
+            let v = (y as f64 + 0.5) / height as f64;
+            let color = radiance(&equirectangular(u, v));
+            Rgb([color.x() as f32, color.y() as f32, color.z() as f32])
+        });
+        Self::new(image, 0.0, 1.0)
+    }
+
+    pub fn open(
+        path: &Path,
+        rotation: f64,
+        intensity: f64,
+    ) -> ImageResult<EnvironmentMap> {
+        let image = image::open(path)?.into_rgb32f();
+        Ok(Self::new(image, rotation, intensity))
+    }
+
+    // image coordinates in [0, 1) of a unit direction
+    fn uv(&self, direction: &Vec3) -> (f64, f64) {
+        let phi = direction.x().atan2(-direction.z()) - self.rotation;
+        let theta = direction.y().clamp(-1.0, 1.0).acos();
+        let u = (0.5 + phi / (2.0 * PI)).rem_euclid(1.0);
+        (u, theta / PI)
+    }
+
+    // inverse of `uv`
+    fn direction(&self, u: f64, v: f64) -> Vec3 {
+        equirectangular(u + self.rotation / (2.0 * PI), v)
+    }
+
+    fn pixel(&self, u: f64, v: f64) -> (usize, usize) {
+        let (width, height) = self.image.dimensions();
+        let x = ((u * width as f64) as usize).min(width as usize - 1);
+        let y = ((v * height as f64) as usize).min(height as usize - 1);
+        (x, y)
+    }
+}
+
+impl Environment for EnvironmentMap {
+    fn radiance(&self, direction: &Vec3) -> Color {
+        let (u, v) = self.uv(direction);
+        let (x, y) = self.pixel(u, v);
+        let [r, g, b] = self.image.get_pixel(x as u32, y as u32).0;
+        self.intensity * Color::new(r as f64, g as f64, b as f64)
+    }
+
+    fn pdf_value(&self, direction: &Vec3) -> f64 {
+        let (u, v) = self.uv(direction);
+        let sin_theta = (PI * v).sin();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+
+        let (x, y) = self.pixel(u, v);
+        let width = self.image.width() as usize;
+        // from the unit square to the sphere
+        self.densities[y * width + x] / (2.0 * PI * PI * sin_theta)
+    }
+
+    fn random(&self) -> Vec3 {
+        let mut rng = rand::thread_rng();
+        let y = sample(&self.rows, rng.gen());
+        let x = sample(&self.columns[y], rng.gen());
+
+        let (width, height) = self.image.dimensions();
+        let u = (x as f64 + rng.gen::<f64>()) / width as f64;
+        let v = (y as f64 + rng.gen::<f64>()) / height as f64;
+
+        self.direction(u, v)
+    }
+}
+
+// Unit direction of image coordinates without rotation
+fn equirectangular(u: f64, v: f64) -> Vec3 {
+    let phi = 2.0 * PI * (u - 0.5);
+    let theta = PI * v;
+    Vec3::new(
+        theta.sin() * phi.sin(),
+        theta.cos(),
+        -theta.sin() * phi.cos(),
+    )
+}
+
+// Running sums, the last one is the total
+fn cumulative(values: &[f64]) -> Vec<f64> {
+    values
+        .iter()
+        .scan(0.0, |sum, v| {
+            *sum += v;
+            Some(*sum)
+        })
+        .collect()
+}
+
+// Index of the entry a uniform `xi` in [0, 1) falls in
+fn sample(cdf: &[f64], xi: f64) -> usize {
+    let target = xi * cdf.last().copied().unwrap_or(0.0);
+    cdf.partition_point(|&c| c <= target).min(cdf.len() - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a dim map with a small bright patch, rotated
+    fn map() -> EnvironmentMap {
+        let image = Rgb32FImage::from_fn(32, 16, |x, y| {
+            if (20..23).contains(&x) && (3..5).contains(&y) {
+                Rgb([50.0, 40.0, 30.0])
+            } else {
+                Rgb([0.1, 0.2, 0.3])
+            }
+        });
+        EnvironmentMap::new(image, 40.0, 2.0)
+    }
+
+    // midpoint rule over the image, the solid angle of a cell is
+    // 2 pi^2 sin(theta) du dv
+    fn integrate(map: &EnvironmentMap, f: impl Fn(&Vec3) -> f64) -> f64 {
+        let (columns, rows) = (512, 256);
+        let mut integral = 0.0;
+        for j in 0..rows {
+            for i in 0..columns {
+                let u = (i as f64 + 0.5) / columns as f64;
+                let v = (j as f64 + 0.5) / rows as f64;
+                integral += f(&map.direction(u, v)) * 2.0 * PI * PI
+                    / (columns * rows) as f64
+                    * (PI * v).sin();
+            }
+        }
+        integral
+    }
+
+    #[test]
+    fn density_integrates_to_one() {
+        let map = map();
+        let integral = integrate(&map, |direction| map.pdf_value(direction));
+        assert!((integral - 1.0).abs() < 0.01, "integral {}", integral);
+    }
+
+    #[test]
+    fn generates_where_the_density_is_positive() {
+        let map = map();
+        for _ in 0..1000 {
+            assert!(map.pdf_value(&map.random()) > 0.0);
+        }
+    }
+
+    #[test]
+    fn importance_sampling_is_unbiased() {
+        let map = map();
+        let expected = integrate(&map, |direction| map.radiance(direction).y());
+        let n = 100_000;
+        let estimate = (0..n)
+            .map(|_| {
+                let direction = map.random();
+                map.radiance(&direction).y() / map.pdf_value(&direction)
+            })
+            .sum::<f64>()
+            / n as f64;
+        assert!(
+            (estimate / expected - 1.0).abs() < 0.02,
+            "{} vs {}",
+            estimate,
+            expected
+        );
+    }
+}
@@ -0,0 +1,265 @@
+use std::{
+    error::Error,
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use exr::prelude::f16;
+use image::{
+    codecs::{
+        hdr::HdrEncoder,
+        pnm::{PnmEncoder, PnmSubtype, SampleEncoding},
+    },
+    ExtendedColorType, ImageEncoder, ImageFormat, ImageResult, Rgb, RgbImage,
+};
+
+use crate::vec3::Color;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum OutputFormat {
+    Png,
+    Jpeg,
+    Bmp,
+    Tga,
+    Ppm,
+    Exr,
+    Hdr,
+    Pfm,
+}
+
+impl OutputFormat {
+    pub const ALL: [OutputFormat; 8] = [
+        OutputFormat::Png,
+        OutputFormat::Jpeg,
+        OutputFormat::Bmp,
+        OutputFormat::Tga,
+        OutputFormat::Ppm,
+        OutputFormat::Exr,
+        OutputFormat::Hdr,
+        OutputFormat::Pfm,
+    ];
+
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        Self::ALL
+            .into_iter()
+            .find(|format| format.extensions().contains(&extension.as_str()))
+    }
+
+    pub fn extensions(self) -> &'static [&'static str] {
+        match self {
+            OutputFormat::Png => &["png"],
+            OutputFormat::Jpeg => &["jpg", "jpeg"],
+            OutputFormat::Bmp => &["bmp"],
+            OutputFormat::Tga => &["tga"],
+            OutputFormat::Ppm => &["ppm"],
+            OutputFormat::Exr => &["exr"],
+            OutputFormat::Hdr => &["hdr"],
+            OutputFormat::Pfm => &["pfm"],
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ExrPrecision {
+    Half,
+    Float,
+}
+
+pub struct Framebuffer {
+    width: u32,
+    height: u32,
+    pixels: Vec<Color>,
+}
+
+impl Framebuffer {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![Color::ZERO; (width * height) as usize],
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> Color {
+        self.pixels[(y * self.width + x) as usize]
+    }
+
+    pub fn set_row(&mut self, y: u32, row: &[Color]) {
+        let start = (y * self.width) as usize;
+        self.pixels[start..start + self.width as usize].copy_from_slice(row);
+    }
+
+    pub fn to_rgb8(&self) -> RgbImage {
+        RgbImage::from_fn(self.width, self.height, |x, y| {
+            Rgb(self.get(x, y).to_rgb8())
+        })
+    }
+
+    fn to_rgb32f(&self) -> Vec<Rgb<f32>> {
+        self.pixels
+            .iter()
+            .map(|c| Rgb([c.x() as f32, c.y() as f32, c.z() as f32]))
+            .collect()
+    }
+
+    pub fn save(
+        &self,
+        path: &Path,
+        exr_precision: ExrPrecision,
+    ) -> Result<(), Box<dyn Error>> {
+        let format = OutputFormat::from_path(path).ok_or_else(|| {
+            format!("unsupported image format for `{}`", path.display())
+        })?;
+
+        match format {
+            OutputFormat::Png => self.save_ldr(path, ImageFormat::Png)?,
+            OutputFormat::Jpeg => self.save_ldr(path, ImageFormat::Jpeg)?,
+            OutputFormat::Bmp => self.save_ldr(path, ImageFormat::Bmp)?,
+            OutputFormat::Tga => self.save_ldr(path, ImageFormat::Tga)?,
+            OutputFormat::Ppm => {
+                let mut out = BufWriter::new(File::create(path)?);
+                self.write_ppm(&mut out)?;
+                out.flush()?;
+            }
+            OutputFormat::Exr => self.save_exr(path, exr_precision)?,
+            OutputFormat::Hdr => {
+                self.write_hdr(BufWriter::new(File::create(path)?))?
+            }
+            OutputFormat::Pfm => {
+                let mut out = BufWriter::new(File::create(path)?);
+                self.write_pfm(&mut out)?;
+                out.flush()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn save_ldr(&self, path: &Path, format: ImageFormat) -> ImageResult<()> {
+        self.to_rgb8().save_with_format(path, format)
+    }
+
+    pub fn write_ppm<W: Write>(&self, out: W) -> ImageResult<()> {
+        PnmEncoder::new(out)
+            .with_subtype(PnmSubtype::Pixmap(SampleEncoding::Binary))
+            .write_image(
+                self.to_rgb8().as_raw(),
+                self.width,
+                self.height,
+                ExtendedColorType::Rgb8,
+            )
+    }
+
+    pub fn save_exr(
+        &self,
+        path: &Path,
+        precision: ExrPrecision,
+    ) -> exr::error::UnitResult {
+        let (width, height) = (self.width as usize, self.height as usize);
+        let pixel = |x: usize, y: usize| self.get(x as u32, y as u32);
+
+        match precision {
+            ExrPrecision::Half => {
+                exr::prelude::write_rgb_file(path, width, height, |x, y| {
+                    let c = pixel(x, y);
+                    (
+                        f16::from_f64(c.x()),
+                        f16::from_f64(c.y()),
+                        f16::from_f64(c.z()),
+                    )
+                })
+            }
+            ExrPrecision::Float => {
+                exr::prelude::write_rgb_file(path, width, height, |x, y| {
+                    let c = pixel(x, y);
+                    (c.x() as f32, c.y() as f32, c.z() as f32)
+                })
+            }
+        }
+    }
+
+    pub fn write_hdr<W: Write>(&self, out: W) -> ImageResult<()> {
+        HdrEncoder::new(out).encode(
+            &self.to_rgb32f(),
+            self.width as usize,
+            self.height as usize,
+        )
+    }
+
+    // PFM stores rows bottom to top, the negative scale marks little endian
+    pub fn write_pfm<W: Write>(&self, mut out: W) -> std::io::Result<()> {
+        write!(out, "PF\n{} {}\n-1.0\n", self.width, self.height)?;
+
+        for y in (0..self.height).rev() {
+            for x in 0..self.width {
+                let c = self.get(x, y);
+                for channel in [c.x(), c.y(), c.z()] {
+                    out.write_all(&(channel as f32).to_le_bytes())?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_the_format_from_the_extension() {
+        let format = |path: &str| OutputFormat::from_path(Path::new(path));
+        for output in OutputFormat::ALL {
+            for extension in output.extensions() {
+                assert_eq!(format(&format!("out.{}", extension)), Some(output));
+            }
+        }
+        assert_eq!(format("out.JPEG"), Some(OutputFormat::Jpeg));
+        assert_eq!(format("renders/out.v2.png"), Some(OutputFormat::Png));
+
+        assert_eq!(format("out.gif"), None);
+        assert_eq!(format("out.png.bak"), None);
+        assert_eq!(format("out"), None);
+        assert_eq!(format(".png"), None);
+    }
+
+    #[test]
+    fn writes_pfm_bottom_row_first() {
+        let mut framebuffer = Framebuffer::new(2, 2);
+        framebuffer.set_row(0, &[Color::new(1.0, 2.0, 3.0), Color::ZERO]);
+        framebuffer.set_row(1, &[Color::new(0.5, 0.25, 4.0), Color::ONE]);
+        let mut pfm = Vec::new();
+        framebuffer.write_pfm(&mut pfm).unwrap();
+
+        // the negative scale marks little endian floats
+        let header = b"PF\n2 2\n-1.0\n";
+        assert_eq!(&pfm[..header.len()], header);
+
+        let floats: Vec<f32> = pfm[header.len()..]
+            .chunks_exact(4)
+            .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()))
+            .collect();
+        // rows go bottom to top, so the last row comes first
+        let mut read = Framebuffer::new(2, 2);
+        for (y, row) in floats.chunks_exact(6).rev().enumerate() {
+            let row: Vec<Color> = row
+                .chunks_exact(3)
+                .map(|c| Color::new(c[0] as f64, c[1] as f64, c[2] as f64))
+                .collect();
+            read.set_row(y as u32, &row);
+        }
+        assert_eq!(floats.len(), 12);
+        assert_eq!(read.pixels, framebuffer.pixels);
+    }
+}
@@ -0,0 +1,598 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt::{self, Display},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use ::image::{Rgb, RgbImage};
+use gltf::{
+    buffer, camera::Projection, image, khr_lights_punctual::Kind, mesh::Mode,
+    texture::WrappingMode,
+};
+
+use crate::{
+    bvh::Bvh,
+    hit::World,
+    light::{DirectionalLight, Light, PointLight, SpotLight},
+    material::{Material, Principled},
+    scenes::Scene,
+    texture::{ImageTexture, SolidColor, Texture},
+    triangle::TriangleMesh,
+    vec3::{Color, Point3, Vec3},
+};
+
+#[derive(Debug)]
+pub enum GltfError {
+    Gltf(PathBuf, gltf::Error),
+    Invalid(PathBuf, String),
+}
+
+impl Display for GltfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GltfError::Gltf(path, err) => {
+                write!(f, "{}: {}", path.display(), err)
+            }
+            GltfError::Invalid(path, message) => {
+                write!(f, "{}: {}", path.display(), message)
+            }
+        }
+    }
+}
+
+impl Error for GltfError {}
+
+// column major, like glTF stores them
+type Matrix = [[f64; 4]; 4];
+
+const IDENTITY: Matrix = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+];
+
+// Loads the default scene of a .gltf or .glb file. Node transforms are baked
+// into the meshes, the first perspective camera becomes the scene camera.
+pub fn load(path: &Path) -> Result<Scene, GltfError> {
+    let (document, buffers, images) = gltf::import(path)
+        .map_err(|err| GltfError::Gltf(path.to_path_buf(), err))?;
+    let gltf_scene = document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+        .ok_or_else(|| {
+            GltfError::Invalid(path.to_path_buf(), String::from("no scene"))
+        })?;
+
+    let mut loader = Loader {
+        path,
+        buffers: &buffers,
+        images: &images,
+        textures: HashMap::new(),
+        materials: HashMap::new(),
+        world: World::new(),
+        min: Point3::from_float(f64::INFINITY),
+        max: Point3::from_float(f64::NEG_INFINITY),
+        camera: None,
+        lights: Vec::new(),
+    };
+    for node in gltf_scene.nodes() {
+        loader.node(&node, &IDENTITY)?;
+    }
+
+    if loader.world.is_empty() {
+        return Err(GltfError::Invalid(
+            path.to_path_buf(),
+            String::from("no triangles"),
+        ));
+    }
+
+    let center = 0.5 * (loader.min + loader.max);
+    let size = (loader.max - loader.min).length();
+
+    let mut scene = Scene {
+        aperture: 0.0,
+        ..Scene::default()
+    };
+    match loader.camera {
+        Some(camera) => {
+            scene.lookfrom = camera.position;
+            scene.lookat = camera.position + camera.forward;
+            scene.vup = camera.up;
+            scene.vfov = camera.yfov.to_degrees();
+            scene.dist_to_focus = (center - camera.position).length();
+            if let Some(aspect_ratio) = camera.aspect_ratio {
+                scene.set_aspect_ratio(aspect_ratio);
+            }
+        }
+        // frame the whole model, looking down -z like a glTF camera would
+        None => {
+            let distance = 0.5 * size / (scene.vfov.to_radians() / 2.0).sin();
+            scene.lookat = center;
+            scene.lookfrom = center + Vec3::from_z(distance);
+            scene.dist_to_focus = distance;
+        }
+    }
+
+    scene.punctual_lights = loader.lights;
+    scene.world = vec![Box::new(Bvh::new(loader.world, 0.0, 1.0))];
+    Ok(scene)
+}
+
+struct GltfCamera {
+    position: Point3,
+    forward: Vec3,
+    up: Vec3,
+    yfov: f64,
+    aspect_ratio: Option<f64>,
+}
+
+struct Loader<'a> {
+    path: &'a Path,
+    buffers: &'a [buffer::Data],
+    images: &'a [image::Data],
+    textures: HashMap<usize, Arc<ImageTexture>>,
+    materials: HashMap<Option<usize>, Arc<dyn Material>>,
+    world: World,
+    min: Point3,
+    max: Point3,
+    camera: Option<GltfCamera>,
+    lights: Vec<Box<dyn Light>>,
+}
+
+impl Loader<'_> {
+    fn node(
+        &mut self,
+        node: &gltf::Node,
+        parent: &Matrix,
+    ) -> Result<(), GltfError> {
+        let local = node.transform().matrix().map(|c| c.map(|x| x as f64));
+        let matrix = multiply(parent, &local);
+
+        if let Some(mesh) = node.mesh() {
+            for primitive in mesh.primitives() {
+                self.primitive(&primitive, &matrix)?;
+            }
+        }
+
+        if let Some(camera) = node.camera() {
+            if let (None, Projection::Perspective(perspective)) =
+                (&self.camera, camera.projection())
+            {
+                self.camera = Some(GltfCamera {
+                    position: transform_point(&matrix, Point3::ZERO),
+                    forward: transform_vector(&matrix, Vec3::from_z(-1.0))
+                        .normalized(),
+                    up: transform_vector(&matrix, Vec3::from_y(1.0))
+                        .normalized(),
+                    yfov: perspective.yfov() as f64,
+                    aspect_ratio: perspective.aspect_ratio().map(f64::from),
+                });
+            }
+        }
+
+        if let Some(light) = node.light() {
+            let [r, g, b] = light.color().map(f64::from);
+            let intensity = light.intensity() as f64 * Color::new(r, g, b);
+            let position = transform_point(&matrix, Point3::ZERO);
+            // lights shine down their local -z
+            let direction =
+                transform_vector(&matrix, Vec3::from_z(-1.0)).normalized();
+
+            let light: Box<dyn Light> = match light.kind() {
+                Kind::Point => Box::new(PointLight::new(position, intensity)),
+                Kind::Spot {
+                    inner_cone_angle,
+                    outer_cone_angle,
+                } => Box::new(SpotLight::new(
+                    position,
+                    direction,
+                    intensity,
+                    (inner_cone_angle as f64).to_degrees(),
+                    (outer_cone_angle as f64).to_degrees(),
+                )),
+                Kind::Directional => {
+                    Box::new(DirectionalLight::new(direction, intensity, 0.0))
+                }
+            };
+            self.lights.push(light);
+        }
+
+        for child in node.children() {
+            self.node(&child, &matrix)?;
+        }
+
+        Ok(())
+    }
+
+    fn primitive(
+        &mut self,
+        primitive: &gltf::Primitive,
+        matrix: &Matrix,
+    ) -> Result<(), GltfError> {
+        if primitive.mode() != Mode::Triangles {
+            return Ok(());
+        }
+
+        let buffers = self.buffers;
+        let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+        let positions: Vec<Point3> = match reader.read_positions() {
+            Some(positions) => positions
+                .map(|[x, y, z]| {
+                    let p = Point3::new(x as f64, y as f64, z as f64);
+                    transform_point(matrix, p)
+                })
+                .collect(),
+            None => return Ok(()),
+        };
+        let normals: Option<Vec<Vec3>> = reader.read_normals().map(|normals| {
+            normals
+                .map(|[x, y, z]| {
+                    let n = Vec3::new(x as f64, y as f64, z as f64);
+                    transform_normal(matrix, n)
+                })
+                .collect()
+        });
+        let uv_set = uv_set(&primitive.material()).map_err(|message| {
+            GltfError::Invalid(self.path.to_path_buf(), message)
+        })?;
+        let uvs: Option<Vec<(f64, f64)>> =
+            reader.read_tex_coords(uv_set).map(|uvs| {
+                uvs.into_f32().map(|[u, v]| (u as f64, v as f64)).collect()
+            });
+        let indices: Vec<usize> = match reader.read_indices() {
+            Some(indices) => indices.into_u32().map(|i| i as usize).collect(),
+            None => (0..positions.len()).collect(),
+        };
+
+        let indices: Vec<[usize; 3]> = indices
+            .chunks_exact(3)
+            .map(|i| [i[0], i[1], i[2]])
+            .collect();
+        if indices.is_empty() {
+            return Ok(());
+        }
+        for p in &positions {
+            for c in 0..3 {
+                self.min[c] = self.min[c].min(p[c]);
+                self.max[c] = self.max[c].max(p[c]);
+            }
+        }
+
+        let vertex_count = positions.len();
+        let material = self.material(primitive.material());
+        let mesh = TriangleMesh::new(
+            positions,
+            normals.filter(|n| n.len() == vertex_count),
+            uvs.filter(|uv| uv.len() == vertex_count),
+            None,
+            indices,
+            material,
+        )
+        .map_err(|err| {
+            GltfError::Invalid(self.path.to_path_buf(), err.to_string())
+        })?;
+        self.world.push(Box::new(mesh));
+
+        Ok(())
+    }
+
+    fn material(&mut self, material: gltf::Material) -> Arc<dyn Material> {
+        if let Some(cached) = self.materials.get(&material.index()) {
+            return cached.clone();
+        }
+
+        let pbr = material.pbr_metallic_roughness();
+        let [r, g, b, _alpha] = pbr.base_color_factor().map(f64::from);
+        let base_color = self.texture(
+            pbr.base_color_texture().map(|info| info.texture()),
+            Color::new(r, g, b),
+            true,
+        );
+        // roughness in the green and metalness in the blue channel
+        let metallic_roughness = self.texture(
+            pbr.metallic_roughness_texture().map(|info| info.texture()),
+            Color::new(
+                1.0,
+                pbr.roughness_factor() as f64,
+                pbr.metallic_factor() as f64,
+            ),
+            false,
+        );
+        let [r, g, b] = material.emissive_factor().map(f64::from);
+        let strength = material.emissive_strength().unwrap_or(1.0) as f64;
+        let emission = self.texture(
+            material.emissive_texture().map(|info| info.texture()),
+            strength * Color::new(r, g, b),
+            true,
+        );
+        // in the red channel
+        let transmission =
+            match material.transmission() {
+                Some(transmission) => Arc::new(Channel(
+                    self.texture(
+                        transmission
+                            .transmission_texture()
+                            .map(|t| t.texture()),
+                        Color::from_float(
+                            transmission.transmission_factor() as f64
+                        ),
+                        false,
+                    ),
+                    0,
+                )) as Arc<dyn Texture>,
+                None => Arc::new(SolidColor::new(Color::ZERO)),
+            };
+        // `Principled::specular` 0.5 is the default index of 1.5
+        let ior = material.ior().unwrap_or(1.5) as f64;
+        let specular = ((ior - 1.0) / (ior + 1.0)).powi(2) / 0.08;
+
+        let converted: Arc<dyn Material> = Arc::new(Principled {
+            metallic: Arc::new(Channel(metallic_roughness.clone(), 2)),
+            roughness: Arc::new(Channel(metallic_roughness, 1)),
+            specular: Arc::new(SolidColor::new(Color::from_float(specular))),
+            transmission,
+            emission,
+            ..Principled::new(base_color)
+        });
+        self.materials.insert(material.index(), converted.clone());
+        converted
+    }
+
+    // Color textures are sRGB encoded, the scalar ones linear
+    fn texture(
+        &mut self,
+        texture: Option<gltf::Texture>,
+        factor: Color,
+        srgb: bool,
+    ) -> Arc<dyn Texture> {
+        let texture = match texture {
+            Some(texture) => texture,
+            None => return Arc::new(SolidColor::new(factor)),
+        };
+
+        let index = texture.source().index();
+        let images = self.images;
+        let image = self
+            .textures
+            .entry(index)
+            .or_insert_with(|| {
+                Arc::new(ImageTexture::new(rgb_image(&images[index])))
+            })
+            .clone();
+        let sampler = texture.sampler();
+
+        Arc::new(GltfTexture {
+            image,
+            factor,
+            wrap: [sampler.wrap_s(), sampler.wrap_t()],
+            srgb,
+        })
+    }
+}
+
+// One channel of a texture in all three, glTF packs several scalar textures
+// in one image
+struct Channel(Arc<dyn Texture>, usize);
+
+impl Texture for Channel {
+    fn value(&self, u: f64, v: f64, p: &Vec3) -> Color {
+        Color::from_float(self.0.value(u, v, p)[self.1])
+    }
+}
+
+// glTF scales textures by a factor, wraps their coordinates and has v
+// pointing down the image
+struct GltfTexture {
+    image: Arc<ImageTexture>,
+    factor: Color,
+    wrap: [WrappingMode; 2],
+    srgb: bool,
+}
+
+impl Texture for GltfTexture {
+    fn value(&self, u: f64, v: f64, p: &Vec3) -> Color {
+        let u = wrap(u, self.wrap[0]);
+        let v = wrap(v, self.wrap[1]);
+        let texel = self.image.value(u, 1.0 - v, p);
+        if self.srgb {
+            let [r, g, b] =
+                [texel.x(), texel.y(), texel.z()].map(srgb_to_linear);
+            self.factor * Color::new(r, g, b)
+        } else {
+            self.factor * texel
+        }
+    }
+}
+
+fn srgb_to_linear(x: f64) -> f64 {
+    if x <= 0.04045 {
+        x / 12.92
+    } else {
+        ((x + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+// The uv set the textures of `material` read. A hit has only one pair of uvs,
+// so they all have to use the same
+fn uv_set(material: &gltf::Material) -> Result<u32, String> {
+    let pbr = material.pbr_metallic_roughness();
+    let mut sets = [
+        pbr.base_color_texture().map(|info| info.tex_coord()),
+        pbr.metallic_roughness_texture()
+            .map(|info| info.tex_coord()),
+        material.emissive_texture().map(|info| info.tex_coord()),
+        material
+            .transmission()
+            .and_then(|transmission| transmission.transmission_texture())
+            .map(|info| info.tex_coord()),
+    ]
+    .into_iter()
+    .flatten();
+
+    let first = sets.next().unwrap_or(0);
+    match sets.find(|&set| set != first) {
+        Some(other) => Err(format!(
+            "material `{}` reads textures with uv sets {} and {}, only one \
+             is supported",
+            material.name().unwrap_or_default(),
+            first,
+            other
+        )),
+        None => Ok(first),
+    }
+}
+
+fn wrap(x: f64, mode: WrappingMode) -> f64 {
+    match mode {
+        WrappingMode::ClampToEdge => x.clamp(0.0, 1.0),
+        WrappingMode::Repeat => x.rem_euclid(1.0),
+        WrappingMode::MirroredRepeat => {
+            let x = x.rem_euclid(2.0);
+            if x > 1.0 {
+                2.0 - x
+            } else {
+                x
+            }
+        }
+    }
+}
+
+// Keeps the first three channels of a decoded image as they are, metallic and
+// roughness live in the green and blue ones
+fn rgb_image(data: &image::Data) -> RgbImage {
+    use image::Format;
+
+    let (channels, depth) = match data.format {
+        Format::R8 => (1, 1),
+        Format::R8G8 => (2, 1),
+        Format::R8G8B8 => (3, 1),
+        Format::R8G8B8A8 => (4, 1),
+        Format::R16 => (1, 2),
+        Format::R16G16 => (2, 2),
+        Format::R16G16B16 => (3, 2),
+        Format::R16G16B16A16 => (4, 2),
+        Format::R32G32B32FLOAT => (3, 4),
+        Format::R32G32B32A32FLOAT => (4, 4),
+    };
+
+    let channel = |bytes: &[u8]| match depth {
+        1 => bytes[0],
+        2 => (u16::from_ne_bytes([bytes[0], bytes[1]]) >> 8) as u8,
+        _ => {
+            let c =
+                f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+            (255.0 * c.clamp(0.0, 1.0)).round() as u8
+        }
+    };
+
+    RgbImage::from_fn(data.width, data.height, |x, y| {
+        let pixel = (y * data.width + x) as usize * channels * depth;
+        let c = |k: usize| channel(&data.pixels[pixel + k * depth..]);
+        Rgb(match channels {
+            1 => [c(0); 3],
+            2 => [c(0), c(1), 0],
+            _ => [c(0), c(1), c(2)],
+        })
+    })
+}
+
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut m = [[0.0; 4]; 4];
+    for (column, b_column) in m.iter_mut().zip(b) {
+        for (row, value) in column.iter_mut().enumerate() {
+            *value = (0..4).map(|k| a[k][row] * b_column[k]).sum();
+        }
+    }
+    m
+}
+
+fn transform_vector(m: &Matrix, v: Vec3) -> Vec3 {
+    Vec3::new(
+        m[0][0] * v.x() + m[1][0] * v.y() + m[2][0] * v.z(),
+        m[0][1] * v.x() + m[1][1] * v.y() + m[2][1] * v.z(),
+        m[0][2] * v.x() + m[1][2] * v.y() + m[2][2] * v.z(),
+    )
+}
+
+fn transform_point(m: &Matrix, p: Point3) -> Point3 {
+    transform_vector(m, p) + Vec3::new(m[3][0], m[3][1], m[3][2])
+}
+
+// Normals transform with the cofactor matrix, the determinant it carries
+// flips them together with the winding of mirrored meshes
+fn transform_normal(m: &Matrix, n: Vec3) -> Vec3 {
+    let axis = |c: usize| Vec3::new(m[c][0], m[c][1], m[c][2]);
+    let (x, y, z) = (axis(0), axis(1), axis(2));
+    (n.x() * y.cross(z) + n.y() * z.cross(x) + n.z() * x.cross(y)).normalized()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        hit::Hittable,
+        ray::Ray,
+        testing::{error, fixture},
+    };
+
+    #[test]
+    fn loads_meshes_and_cameras() {
+        let scene = load(&fixture("triangle.gltf")).unwrap();
+
+        assert_eq!(scene.lookfrom, Point3::from_z(5.0));
+        assert_eq!(scene.lookat, Point3::from_z(4.0));
+        assert!((scene.vfov - 0.5f64.to_degrees()).abs() < 1e-6);
+        assert_eq!(scene.aspect_ratio, 2.0);
+
+        // the base color reads the second uv set
+        let ray =
+            Ray::new(Point3::new(0.25, 0.25, 1.0), Vec3::from_z(-1.0), 0.0);
+        let rec = scene.world.hit(&ray, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.u - 0.625).abs() < 1e-6 && (rec.v - 0.625).abs() < 1e-6);
+    }
+
+    #[test]
+    fn reports_textures_on_different_uv_sets() {
+        let path = fixture("mixed_uv_sets.gltf");
+        assert_eq!(
+            error(load(&path)),
+            format!(
+                "{}: material `mixed` reads textures with uv sets 0 and 1, \
+                 only one is supported",
+                path.display()
+            )
+        );
+    }
+
+    #[test]
+    fn decodes_srgb_color_textures_only() {
+        let image = Arc::new(ImageTexture::new(RgbImage::from_pixel(
+            1,
+            1,
+            Rgb([128, 128, 128]),
+        )));
+        let texture = |srgb| GltfTexture {
+            image: image.clone(),
+            factor: Color::from_float(2.0),
+            wrap: [WrappingMode::Repeat; 2],
+            srgb,
+        };
+
+        // 128 is about 0.216 once linear
+        let color = texture(true).value(0.5, 0.5, &Point3::ZERO);
+        assert!((color.x() - 2.0 * 0.2158605).abs() < 1e-6);
+        assert!(color.x() == color.y() && color.y() == color.z());
+        let data = texture(false).value(0.5, 0.5, &Point3::ZERO);
+        assert_eq!(data, Color::from_float(2.0 * 128.0 / 255.0));
+    }
+
+    #[test]
+    fn reports_missing_files() {
+        let path = fixture("missing.gltf");
+        let message = error(load(&path));
+        assert!(message.starts_with(&*path.to_string_lossy()), "{}", message);
+    }
+}
@@ -1,10 +1,12 @@
 use std::sync::Arc;
 
+use rand::Rng;
+
 use crate::{
     aabb::AABB,
     material::Material,
     ray::Ray,
-    vec3::{Point3, Vec3},
+    vec3::{Color, Point3, Vec3},
 };
 
 pub struct HitRecord {
@@ -16,6 +18,11 @@ pub struct HitRecord {
     pub u: f64,
     pub v: f64,
     pub front_face: bool,
+    // index of refraction around the object, 1 unless it's nested in
+    // another dielectric
+    pub outside_index_of_refraction: f64,
+    // interpolated from the vertices of meshes that have colors
+    pub vertex_color: Option<Color>,
 }
 
 impl HitRecord {
@@ -28,16 +35,18 @@ impl HitRecord {
             u: 0.0,
             v: 0.0,
             front_face: false,
+            outside_index_of_refraction: 1.0,
+            vertex_color: None,
         }
     }
 
-    pub fn set_face_normal(&mut self, r: &Ray, outward_normal: Vec3) -> () {
+    pub fn set_face_normal(&mut self, r: &Ray, outward_normal: Vec3) {
         self.front_face = r.direction().dot(outward_normal) < 0.0;
         self.normal =
             if self.front_face { outward_normal } else { -outward_normal }
     }
 
-    pub fn set_u_v(&mut self, u: f64, v: f64) -> () {
+    pub fn set_u_v(&mut self, u: f64, v: f64) {
         self.u = u;
         self.v = v;
     }
@@ -46,6 +55,17 @@ impl HitRecord {
 pub trait Hittable: Send + Sync {
     fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord>;
     fn bounding_box(&self, time0: f64, time1: f64) -> Option<AABB>;
+
+    // Solid angle density of `random` picking `direction` from `origin`,
+    // shapes that can't be sampled as lights keep the 0 default
+    fn pdf_value(&self, _origin: &Point3, _direction: &Vec3) -> f64 {
+        0.0
+    }
+
+    // Direction from `origin` towards a random point on the shape
+    fn random(&self, _origin: &Point3) -> Vec3 {
+        Vec3::from_x(1.0)
+    }
 }
 
 pub type World = Vec<Box<dyn Hittable>>;
@@ -83,6 +103,25 @@ impl Hittable for World {
 
         bounding_box
     }
+
+    // a uniform pick of one object, so the density is the average
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        if self.is_empty() {
+            return 0.0;
+        }
+        self.iter()
+            .map(|object| object.pdf_value(origin, direction))
+            .sum::<f64>()
+            / self.len() as f64
+    }
+
+    fn random(&self, origin: &Point3) -> Vec3 {
+        if self.is_empty() {
+            return Vec3::from_x(1.0);
+        }
+        let i = rand::thread_rng().gen_range(0..self.len());
+        self[i].random(origin)
+    }
 }
 
 pub struct Translate {
@@ -111,13 +150,20 @@ impl Hittable for Translate {
     }
 
     fn bounding_box(&self, time0: f64, time1: f64) -> Option<AABB> {
-        match self.hittable.bounding_box(time0, time1) {
-            Some(output_box) => Some(AABB::new(
+        self.hittable.bounding_box(time0, time1).map(|output_box| {
+            AABB::new(
                 output_box.min() + self.offset,
                 output_box.max() + self.offset,
-            )),
-            _ => None,
-        }
+            )
+        })
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        self.hittable.pdf_value(&(*origin - self.offset), direction)
+    }
+
+    fn random(&self, origin: &Point3) -> Vec3 {
+        self.hittable.random(&(*origin - self.offset))
     }
 }
 
@@ -177,6 +223,22 @@ impl RotateY {
             bounding_box,
         }
     }
+
+    fn to_object(&self, v: Vec3) -> Vec3 {
+        Vec3::new(
+            self.cos_theta * v[0] - self.sin_theta * v[2],
+            v[1],
+            self.sin_theta * v[0] + self.cos_theta * v[2],
+        )
+    }
+
+    fn to_world(&self, v: Vec3) -> Vec3 {
+        Vec3::new(
+            self.cos_theta * v[0] + self.sin_theta * v[2],
+            v[1],
+            -self.sin_theta * v[0] + self.cos_theta * v[2],
+        )
+    }
 }
 
 impl Hittable for RotateY {
@@ -221,4 +283,13 @@ impl Hittable for RotateY {
     fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<AABB> {
         self.bounding_box
     }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        self.hittable
+            .pdf_value(&self.to_object(*origin), &self.to_object(*direction))
+    }
+
+    fn random(&self, origin: &Point3) -> Vec3 {
+        self.to_world(self.hittable.random(&self.to_object(*origin)))
+    }
 }
@@ -0,0 +1,44 @@
+pub mod aabb;
+pub mod aarect;
+pub mod box3;
+pub mod bvh;
+mod camera;
+pub mod constant_medium;
+#[allow(dead_code)]
+mod cube;
+pub mod environment;
+pub mod framebuffer;
+pub mod gltf;
+pub mod hit;
+pub mod light;
+pub mod material;
+pub mod moving_sphere;
+mod obj;
+pub mod pdf;
+mod perlin;
+mod ply;
+pub mod ray;
+mod render;
+pub mod scene_file;
+pub mod scenes;
+pub mod sky;
+mod spectrum;
+pub mod sphere;
+mod stl;
+#[cfg(test)]
+mod testing;
+pub mod texture;
+pub mod triangle;
+pub mod vec3;
+
+pub use camera::Camera;
+pub use environment::Environment;
+pub use framebuffer::Framebuffer;
+pub use hit::{HitRecord, Hittable, World};
+pub use light::Light;
+pub use material::Material;
+pub use ray::Ray;
+pub use render::{render, render_with_stats, Integrator, RenderSettings};
+pub use scenes::Scene;
+pub use texture::Texture;
+pub use vec3::{Color, Point3, Vec3};
@@ -0,0 +1,249 @@
+use std::f64::consts::PI;
+
+use rand::Rng;
+
+use crate::vec3::{Color, Point3, Vec3};
+
+pub struct LightSample {
+    // unit vector from the shaded point towards the light
+    pub direction: Vec3,
+    // how far the shadow ray has to stay clear, infinite for distant lights
+    pub distance: f64,
+    // light arriving along `direction` divided by the density of picking it,
+    // so for a delta light simply the incident radiance
+    pub radiance: Color,
+}
+
+// Lights without a surface, which no bounce can ever hit, so the integrator
+// samples each of them at every hit
+pub trait Light: Send + Sync {
+    fn sample(&self, p: &Point3) -> Option<LightSample>;
+
+    // Radiance seen looking along `direction` out of the scene, for lights
+    // with an extent at infinity that the camera and mirrors can see
+    fn radiance(&self, _direction: &Vec3) -> Color {
+        Color::ZERO
+    }
+}
+
+// Radiates `intensity` equally in all directions, falling off with the square
+// of the distance
+pub struct PointLight {
+    position: Point3,
+    intensity: Color,
+}
+
+impl PointLight {
+    pub fn new(position: Point3, intensity: Color) -> Self {
+        Self {
+            position,
+            intensity,
+        }
+    }
+}
+
+impl Light for PointLight {
+    fn sample(&self, p: &Point3) -> Option<LightSample> {
+        let to_light = self.position - *p;
+        let distance_squared = to_light.length_squared();
+        if distance_squared <= 0.0 {
+            return None;
+        }
+
+        let distance = distance_squared.sqrt();
+        Some(LightSample {
+            direction: to_light / distance,
+            distance,
+            radiance: self.intensity / distance_squared,
+        })
+    }
+}
+
+// A point light restricted to a cone around `direction`, at full intensity
+// within `inner_angle` and fading out smoothly up to `outer_angle`. Both are
+// half angles in degrees
+pub struct SpotLight {
+    position: Point3,
+    direction: Vec3,
+    intensity: Color,
+    cos_inner: f64,
+    cos_outer: f64,
+}
+
+impl SpotLight {
+    pub fn new(
+        position: Point3,
+        direction: Vec3,
+        intensity: Color,
+        inner_angle: f64,
+        outer_angle: f64,
+    ) -> Self {
+        let outer_angle = outer_angle.clamp(0.0, 180.0);
+        let inner_angle = inner_angle.clamp(0.0, outer_angle);
+        Self {
+            position,
+            direction: direction.normalized(),
+            intensity,
+            cos_inner: inner_angle.to_radians().cos(),
+            cos_outer: outer_angle.to_radians().cos(),
+        }
+    }
+
+    fn falloff(&self, cos_theta: f64) -> f64 {
+        if cos_theta >= self.cos_inner {
+            1.0
+        } else if cos_theta <= self.cos_outer {
+            0.0
+        } else {
+            let t = (cos_theta - self.cos_outer)
+                / (self.cos_inner - self.cos_outer);
+            t * t * (3.0 - 2.0 * t)
+        }
+    }
+}
+
+impl Light for SpotLight {
+    fn sample(&self, p: &Point3) -> Option<LightSample> {
+        let to_light = self.position - *p;
+        let distance_squared = to_light.length_squared();
+        if distance_squared <= 0.0 {
+            return None;
+        }
+
+        let distance = distance_squared.sqrt();
+        let direction = to_light / distance;
+        let falloff = self.falloff(-direction.dot(self.direction));
+        if falloff <= 0.0 {
+            return None;
+        }
+
+        Some(LightSample {
+            direction,
+            distance,
+            radiance: falloff * self.intensity / distance_squared,
+        })
+    }
+}
+
+// Light from infinitely far away travelling along `direction`, like the sun.
+// `irradiance` is measured on a surface facing the light, a nonzero
+// `angular_diameter` in degrees spreads it over a disk for soft shadows
+pub struct DirectionalLight {
+    to_light: Vec3,
+    irradiance: Color,
+    cos_half_angle: f64,
+}
+
+impl DirectionalLight {
+    pub fn new(
+        direction: Vec3,
+        irradiance: Color,
+        angular_diameter: f64,
+    ) -> Self {
+        let half_angle = (angular_diameter / 2.0).clamp(0.0, 90.0);
+        Self {
+            to_light: -direction.normalized(),
+            irradiance,
+            cos_half_angle: half_angle.to_radians().cos(),
+        }
+    }
+}
+
+impl Light for DirectionalLight {
+    fn sample(&self, _p: &Point3) -> Option<LightSample> {
+        // uniform over the cone of the disk, the radiance is the irradiance
+        // spread over its solid angle and the density is one over it, so
+        // each sample carries the irradiance
+        let direction = if self.cos_half_angle < 1.0 {
+            let mut rng = rand::thread_rng();
+            let z = 1.0 + rng.gen::<f64>() * (self.cos_half_angle - 1.0);
+            let phi = 2.0 * PI * rng.gen::<f64>();
+            let sin_theta = (1.0 - z * z).max(0.0).sqrt();
+
+            let (t, b) = self.to_light.orthonormal_basis();
+            phi.cos() * sin_theta * t
+                + phi.sin() * sin_theta * b
+                + z * self.to_light
+        } else {
+            self.to_light
+        };
+
+        Some(LightSample {
+            direction,
+            distance: f64::INFINITY,
+            radiance: self.irradiance,
+        })
+    }
+
+    fn radiance(&self, direction: &Vec3) -> Color {
+        if self.cos_half_angle < 1.0
+            && direction.normalized().dot(self.to_light) >= self.cos_half_angle
+        {
+            self.irradiance / (2.0 * PI * (1.0 - self.cos_half_angle))
+        } else {
+            Color::ZERO
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a spot light at the origin pointing down -y, seen from `angle` degrees
+    // off its axis
+    fn spot_sample(angle: f64) -> Option<LightSample> {
+        let spot = SpotLight::new(
+            Point3::ZERO,
+            Vec3::from_y(-1.0),
+            Color::ONE,
+            20.0,
+            40.0,
+        );
+        let angle = angle.to_radians();
+        spot.sample(&Point3::new(angle.sin(), -angle.cos(), 0.0))
+    }
+
+    #[test]
+    fn spot_light_fades_between_its_angles() {
+        for angle in [0.0, 10.0, 19.9] {
+            let sample = spot_sample(angle).unwrap();
+            assert!((sample.radiance - Color::ONE).length() < 1e-9);
+        }
+
+        let mut previous = 1.0;
+        for angle in [22.0, 30.0, 38.0] {
+            let falloff = spot_sample(angle).unwrap().radiance.x();
+            assert!(falloff > 0.0 && falloff < previous, "{}", falloff);
+            previous = falloff;
+        }
+
+        for angle in [40.1, 90.0, 180.0] {
+            assert!(spot_sample(angle).is_none());
+        }
+    }
+
+    #[test]
+    fn directional_light_samples_stay_in_its_cone() {
+        let direction = Vec3::new(1.0, -2.0, 0.5);
+        let light = DirectionalLight::new(direction, Color::ONE, 10.0);
+        let to_light = -direction.normalized();
+        let cos_half_angle = 5.0_f64.to_radians().cos();
+
+        let mut widest: f64 = 1.0;
+        for _ in 0..10_000 {
+            let sample = light.sample(&Point3::ZERO).unwrap();
+            assert!((sample.direction.length() - 1.0).abs() < 1e-9);
+            let cos = sample.direction.dot(to_light);
+            assert!(cos >= cos_half_angle - 1e-9, "cos {}", cos);
+            widest = widest.min(cos);
+        }
+        // and spread out to its edge
+        assert!(widest.acos().to_degrees() > 4.5);
+
+        // a zero diameter is a single direction
+        let light = DirectionalLight::new(direction, Color::ONE, 0.0);
+        let sample = light.sample(&Point3::ZERO).unwrap();
+        assert!((sample.direction - to_light).length() < 1e-9);
+    }
+}
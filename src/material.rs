@@ -1,20 +1,68 @@
-use std::sync::Arc;
+use std::{
+    f64::consts::PI,
+    ops::{Add, Div, Mul, Sub},
+    sync::Arc,
+};
 
 use rand::Rng;
 
 use crate::{
     hit::HitRecord,
+    pdf::{CosinePdf, MixturePdf, Pdf, SpherePdf},
     ray::Ray,
+    spectrum,
     texture::{SolidColor, Texture},
     vec3::{Color, Point3, Vec3},
 };
 
+pub enum ScatterRecord {
+    // a single outgoing direction, like a mirror or glass, the attenuation is
+    // the whole weight of the bounce
+    Specular { attenuation: Color, ray: Ray },
+    // directions to importance sample, weighted by `Material::eval` over the
+    // density
+    Sampled { pdf: Box<dyn Pdf> },
+}
+
 pub trait Material: Send + Sync {
-    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)>;
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord>;
+
+    // BSDF times the cosine with the normal, for light leaving along `ray_in`
+    // that arrived along `direction`. Only called for sampled scattering
+    fn eval(
+        &self,
+        _ray_in: &Ray,
+        _rec: &HitRecord,
+        _direction: &Vec3,
+    ) -> Color {
+        Color::ZERO
+    }
 
     fn color_emitted(&self, _u: f64, _v: f64, _p: &Point3) -> Color {
         Color::ZERO
     }
+
+    // What fills the object when the surface is a dielectric interface, for
+    // the path to know what it travels through
+    fn interior(&self) -> Option<Interior> {
+        None
+    }
+
+    // Whether scattering changes with `Ray::wavelength`, so that a spectral
+    // path only keeps that wavelength past it
+    fn depends_on_wavelength(&self) -> bool {
+        false
+    }
+}
+
+// The inside of a closed dielectric. Light crossing it keeps exp(-absorption
+// distance) of itself, and where two overlap, like a liquid in a glass, the
+// surfaces of the lower `priority` one are ignored inside the other
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Interior {
+    pub index_of_refraction: Ior,
+    pub absorption: Color,
+    pub priority: u32,
 }
 
 pub struct Lambertian {
@@ -34,60 +82,272 @@ impl Lambertian {
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
-        let mut scatter_direction =
-            rec.normal + Vec3::random_in_unit_sphere().normalized();
+    fn scatter(&self, _ray_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        Some(ScatterRecord::Sampled {
+            pdf: Box::new(CosinePdf::new(rec.normal)),
+        })
+    }
 
-        if scatter_direction.near_zero() {
-            scatter_direction = rec.normal;
+    fn eval(&self, _ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        let cosine = rec.normal.dot(direction.normalized());
+        self.albedo.value_at(rec) * cosine.max(0.0) / PI
+    }
+}
+
+// Rough diffuse surface made of Lambertian facets (Oren and Nayar 1994), with
+// the qualitative model's fit. `sigma` is the standard deviation of the facet
+// angles in degrees, 0 is Lambertian
+pub struct OrenNayar {
+    albedo: Arc<dyn Texture>,
+    a: f64,
+    b: f64,
+}
+
+impl OrenNayar {
+    pub fn new(albedo: Arc<dyn Texture>, sigma: f64) -> Self {
+        let sigma2 = sigma.to_radians().powi(2);
+        Self {
+            albedo,
+            a: 1.0 - sigma2 / (2.0 * (sigma2 + 0.33)),
+            b: 0.45 * sigma2 / (sigma2 + 0.09),
         }
+    }
+}
 
-        let scattered = Ray::new(rec.p, scatter_direction, ray_in.time());
+impl Material for OrenNayar {
+    fn scatter(&self, _ray_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        Some(ScatterRecord::Sampled {
+            pdf: Box::new(CosinePdf::new(rec.normal)),
+        })
+    }
 
-        let attenuation = self.albedo.value(rec.u, rec.v, &rec.p);
-        Some((attenuation, scattered))
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        let wo = -ray_in.direction().normalized();
+        let wi = direction.normalized();
+        let cos_i = rec.normal.dot(wi);
+        let cos_o = rec.normal.dot(wo).clamp(0.0, 1.0);
+        if cos_i <= 0.0 {
+            return Color::ZERO;
+        }
+        let sin_i = (1.0 - cos_i * cos_i).max(0.0).sqrt();
+        let sin_o = (1.0 - cos_o * cos_o).sqrt();
+
+        // cosine of the azimuth between the two directions
+        let cos_phi = if sin_i > 1e-4 && sin_o > 1e-4 {
+            let ti = (wi - cos_i * rec.normal) / sin_i;
+            let to = (wo - cos_o * rec.normal) / sin_o;
+            ti.dot(to).max(0.0)
+        } else {
+            0.0
+        };
+        // sin(alpha) tan(beta), alpha the larger angle to the normal
+        let sin_tan = if cos_i > cos_o {
+            sin_o * sin_i / cos_i
+        } else {
+            sin_i * sin_o / cos_o.max(1e-4)
+        };
+
+        self.albedo.value_at(rec)
+            * ((self.a + self.b * cos_phi * sin_tan) * cos_i / PI)
     }
 }
 
 pub struct Metal {
     albedo: Color,
     fuzz: f64,
+    film: Option<ThinFilm>,
 }
 
 impl Metal {
     pub fn new(albedo: Color, fuzz: f64) -> Self {
-        Self { albedo, fuzz }
+        Self {
+            albedo,
+            fuzz,
+            film: None,
+        }
+    }
+
+    // over a metal with `albedo` as its reflectance head on and towards the
+    // edges
+    pub fn with_thin_film(self, film: ThinFilm) -> Self {
+        Self {
+            film: Some(film),
+            ..self
+        }
+    }
+}
+
+impl Metal {
+    fn reflectance(&self, ray_in: &Ray, rec: &HitRecord) -> Color {
+        match &self.film {
+            Some(film) => {
+                let cosine = -ray_in.direction().normalized().dot(rec.normal);
+                film.reflectance(ray_in, rec, cosine, 1.0, |wavelength| {
+                    let reflectivity = at_wavelength(self.albedo, wavelength);
+                    conductor_index(reflectivity, reflectivity)
+                })
+            }
+            None => self.albedo,
+        }
     }
 }
 
 impl Material for Metal {
-    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
         let reflected = ray_in.direction().reflect(rec.normal).normalized();
-        let scattered = Ray::new(
-            rec.p,
-            reflected + self.fuzz * Vec3::random_in_unit_sphere(),
-            ray_in.time(),
-        );
+        if self.fuzz > 0.0 {
+            return Some(ScatterRecord::Sampled {
+                pdf: Box::new(FuzzPdf {
+                    reflected,
+                    fuzz: self.fuzz,
+                }),
+            });
+        }
 
-        if scattered.direction().dot(rec.normal) > 0.0 {
-            Some((self.albedo, scattered))
-        } else {
-            None
+        if reflected.dot(rec.normal) <= 0.0 {
+            return None;
+        }
+        Some(ScatterRecord::Specular {
+            attenuation: self.reflectance(ray_in, rec),
+            ray: Ray::new(rec.p, reflected, ray_in.time()),
+        })
+    }
+
+    // the fuzzy reflection sampled as is, what goes below the surface is lost
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        if self.fuzz <= 0.0 || direction.dot(rec.normal) <= 0.0 {
+            return Color::ZERO;
+        }
+        let pdf = FuzzPdf {
+            reflected: ray_in.direction().reflect(rec.normal).normalized(),
+            fuzz: self.fuzz,
+        };
+        self.reflectance(ray_in, rec) * pdf.value(direction)
+    }
+
+    fn depends_on_wavelength(&self) -> bool {
+        self.film.is_some()
+    }
+}
+
+// An index of refraction, the dispersive ones vary with the wavelength (in µm
+// in the formulas). Without a wavelength, when rendering in RGB, they're taken
+// at the helium d line indices are quoted for
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Ior {
+    Constant(f64),
+    // a + b / λ²
+    Cauchy { a: f64, b: f64 },
+    // n² = 1 + Σ b λ² / (λ² - c)
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+impl Ior {
+    pub fn at(&self, wavelength: Option<f64>) -> f64 {
+        let lambda2 = (wavelength.unwrap_or(587.6) / 1000.0).powi(2);
+        match *self {
+            Ior::Constant(n) => n,
+            Ior::Cauchy { a, b } => a + b / lambda2,
+            Ior::Sellmeier { b, c } => (1.0
+                + (0..3)
+                    .map(|i| b[i] * lambda2 / (lambda2 - c[i]))
+                    .sum::<f64>())
+            .sqrt(),
         }
     }
+
+    pub fn is_dispersive(&self) -> bool {
+        !matches!(self, Ior::Constant(_))
+    }
 }
 
-pub struct Dielectric {
+impl From<f64> for Ior {
+    fn from(n: f64) -> Self {
+        Ior::Constant(n)
+    }
+}
+
+// A film a few hundred nm thick on a surface, like soap, oil on water or a
+// lens coating, coloring its reflections by interference. `thickness` is in
+// nm, from the average of the texture's channels
+#[derive(Clone)]
+pub struct ThinFilm {
+    thickness: Arc<dyn Texture>,
     index_of_refraction: f64,
 }
 
-impl Dielectric {
-    pub fn new(index_of_refraction: f64) -> Self {
+impl ThinFilm {
+    pub fn new(thickness: Arc<dyn Texture>, index_of_refraction: f64) -> Self {
         Self {
+            thickness,
             index_of_refraction,
         }
     }
 
+    // Reflectance of the film at the hit over a substrate of complex index
+    // `substrate(wavelength)`, lit from a medium of index `outside`. At the
+    // hero wavelength when spectral, as the color under white light otherwise
+    fn reflectance(
+        &self,
+        ray_in: &Ray,
+        rec: &HitRecord,
+        cos_i: f64,
+        outside: f64,
+        substrate: impl Fn(f64) -> Complex,
+    ) -> Color {
+        let thickness = average(self.thickness.value_at(rec)).max(0.0);
+        let at = |wavelength| {
+            thin_film_reflectance(
+                cos_i.clamp(0.0, 1.0),
+                wavelength,
+                thickness,
+                outside,
+                self.index_of_refraction,
+                substrate(wavelength),
+            )
+        };
+
+        match ray_in.wavelength() {
+            Some(wavelength) => Color::from_float(at(wavelength)),
+            None => spectrum::reflectance_rgb(at),
+        }
+    }
+}
+
+pub struct Dielectric {
+    index_of_refraction: Ior,
+    absorption: Color,
+    priority: u32,
+    film: Option<ThinFilm>,
+}
+
+impl Dielectric {
+    pub fn new(index_of_refraction: impl Into<Ior>) -> Self {
+        Self {
+            index_of_refraction: index_of_refraction.into(),
+            absorption: Color::ZERO,
+            priority: 0,
+            film: None,
+        }
+    }
+
+    // per unit of distance inside
+    pub fn with_absorption(self, absorption: Color) -> Self {
+        Self { absorption, ..self }
+    }
+
+    pub fn with_priority(self, priority: u32) -> Self {
+        Self { priority, ..self }
+    }
+
+    // on the outer surface, in place of the Fresnel reflection
+    pub fn with_thin_film(self, film: ThinFilm) -> Self {
+        Self {
+            film: Some(film),
+            ..self
+        }
+    }
+
     pub fn reflectance(cosine: f64, ref_idx: f64) -> f64 {
         let r0 = ((1.0 - ref_idx) / (1.0 + ref_idx)).powi(2);
         r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
@@ -95,11 +355,13 @@ impl Dielectric {
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        let index_of_refraction =
+            self.index_of_refraction.at(ray_in.wavelength());
         let refraction_ratio = if rec.front_face {
-            1.0 / self.index_of_refraction
+            rec.outside_index_of_refraction / index_of_refraction
         } else {
-            self.index_of_refraction
+            index_of_refraction / rec.outside_index_of_refraction
         };
         let unit_direction = ray_in.direction().normalized();
         let cos_theta = -unit_direction.dot(rec.normal).min(1.0);
@@ -107,18 +369,437 @@ impl Material for Dielectric {
 
         let mut rng = rand::thread_rng();
         let cannot_reflect = refraction_ratio * sin_theta > 1.0;
-        let will_reflect =
-            rng.gen::<f64>() < Self::reflectance(cos_theta, refraction_ratio);
 
-        let direction = if cannot_reflect || will_reflect {
-            unit_direction.reflect(rec.normal)
+        // a colored reflectance, reflect about as often and make up for the
+        // rest in the attenuation
+        let (reflectance, probability) = match &self.film {
+            Some(film) => {
+                let (outside, inside) = if rec.front_face {
+                    (rec.outside_index_of_refraction, index_of_refraction)
+                } else {
+                    (index_of_refraction, rec.outside_index_of_refraction)
+                };
+                let reflectance =
+                    film.reflectance(ray_in, rec, cos_theta, outside, |_| {
+                        Complex::real(inside)
+                    });
+                (reflectance, average(reflectance).clamp(0.01, 0.99))
+            }
+            None => {
+                let reflectance =
+                    Self::reflectance(cos_theta, refraction_ratio);
+                (Color::from_float(reflectance), reflectance)
+            }
+        };
+
+        let (direction, attenuation) = if cannot_reflect {
+            (unit_direction.reflect(rec.normal), Color::ONE)
+        } else if rng.gen::<f64>() < probability {
+            (
+                unit_direction.reflect(rec.normal),
+                reflectance / probability,
+            )
         } else {
-            unit_direction.refract(rec.normal, refraction_ratio)
+            (
+                unit_direction.refract(rec.normal, refraction_ratio),
+                (Color::ONE - reflectance) / (1.0 - probability),
+            )
         };
 
         let scattered = Ray::new(rec.p, direction, ray_in.time());
 
-        Some((Color::ONE, scattered))
+        Some(ScatterRecord::Specular {
+            attenuation,
+            ray: scattered,
+        })
+    }
+
+    fn interior(&self) -> Option<Interior> {
+        Some(Interior {
+            index_of_refraction: self.index_of_refraction,
+            absorption: self.absorption,
+            priority: self.priority,
+        })
+    }
+
+    fn depends_on_wavelength(&self) -> bool {
+        self.index_of_refraction.is_dispersive() || self.film.is_some()
+    }
+}
+
+// A metal with the complex index of refraction `eta` + i `k` per channel,
+// rough with the GGX distribution. Roughness comes from the average of the
+// texture's channels, below `SMOOTH_ROUGHNESS` it's a perfect mirror
+pub struct RoughConductor {
+    eta: Color,
+    k: Color,
+    roughness: Arc<dyn Texture>,
+    film: Option<ThinFilm>,
+}
+
+impl RoughConductor {
+    pub fn new(eta: Color, k: Color, roughness: Arc<dyn Texture>) -> Self {
+        Self {
+            eta,
+            k,
+            roughness,
+            film: None,
+        }
+    }
+
+    pub fn with_thin_film(self, film: ThinFilm) -> Self {
+        Self {
+            film: Some(film),
+            ..self
+        }
+    }
+
+    // measured indices at about 650, 550 and 450 nm
+    pub fn gold(roughness: Arc<dyn Texture>) -> Self {
+        Self::new(
+            Color::new(0.143, 0.374, 1.442),
+            Color::new(3.983, 2.385, 1.603),
+            roughness,
+        )
+    }
+
+    pub fn copper(roughness: Arc<dyn Texture>) -> Self {
+        Self::new(
+            Color::new(0.200, 0.924, 1.102),
+            Color::new(3.912, 2.452, 2.142),
+            roughness,
+        )
+    }
+
+    pub fn aluminium(roughness: Arc<dyn Texture>) -> Self {
+        Self::new(
+            Color::new(1.657, 0.880, 0.521),
+            Color::new(9.224, 6.270, 4.837),
+            roughness,
+        )
+    }
+
+    fn fresnel(&self, ray_in: &Ray, rec: &HitRecord, cosine: f64) -> Color {
+        if let Some(film) = &self.film {
+            return film.reflectance(ray_in, rec, cosine, 1.0, |wavelength| {
+                Complex::new(
+                    at_wavelength(self.eta, wavelength),
+                    at_wavelength(self.k, wavelength),
+                )
+            });
+        }
+
+        Color::new(
+            fresnel_conductor(cosine, self.eta.x(), self.k.x()),
+            fresnel_conductor(cosine, self.eta.y(), self.k.y()),
+            fresnel_conductor(cosine, self.eta.z(), self.k.z()),
+        )
+    }
+}
+
+impl Material for RoughConductor {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        let frame = Frame::new(rec.normal);
+        let wo = frame.to_local(-ray_in.direction().normalized());
+        if wo.z() <= 0.0 {
+            return None;
+        }
+
+        match ggx_alpha(self.roughness.as_ref(), rec) {
+            Some(alpha) => Some(ScatterRecord::Sampled {
+                pdf: Box::new(GgxPdf { frame, wo, alpha }),
+            }),
+            None => Some(ScatterRecord::Specular {
+                attenuation: self.fresnel(ray_in, rec, wo.z()),
+                ray: Ray::new(
+                    rec.p,
+                    ray_in.direction().reflect(rec.normal),
+                    ray_in.time(),
+                ),
+            }),
+        }
+    }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        let Some(alpha) = ggx_alpha(self.roughness.as_ref(), rec) else {
+            return Color::ZERO;
+        };
+        let frame = Frame::new(rec.normal);
+        let wo = frame.to_local(-ray_in.direction().normalized());
+        let wi = frame.to_local(direction.normalized());
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return Color::ZERO;
+        }
+
+        let h = (wo + wi).normalized();
+        let g2 =
+            1.0 / (1.0 + smith_lambda(wo, alpha) + smith_lambda(wi, alpha));
+        self.fresnel(ray_in, rec, wo.dot(h))
+            * (ggx_d(h, alpha) * g2 / (4.0 * wo.z()))
+    }
+
+    fn depends_on_wavelength(&self) -> bool {
+        self.film.is_some()
+    }
+}
+
+// Glass with GGX rough surfaces, reflecting and refracting. Roughness as for
+// `RoughConductor`, smooth enough it scatters like `Dielectric`
+pub struct RoughDielectric {
+    index_of_refraction: Ior,
+    roughness: Arc<dyn Texture>,
+    absorption: Color,
+    priority: u32,
+}
+
+impl RoughDielectric {
+    pub fn new(
+        index_of_refraction: impl Into<Ior>,
+        roughness: Arc<dyn Texture>,
+    ) -> Self {
+        Self {
+            index_of_refraction: index_of_refraction.into(),
+            roughness,
+            absorption: Color::ZERO,
+            priority: 0,
+        }
+    }
+
+    // see `Dielectric`
+    pub fn with_absorption(self, absorption: Color) -> Self {
+        Self { absorption, ..self }
+    }
+
+    pub fn with_priority(self, priority: u32) -> Self {
+        Self { priority, ..self }
+    }
+
+    // index of the side opposite the ray over the one it comes from
+    fn relative_eta(&self, ray_in: &Ray, rec: &HitRecord) -> f64 {
+        let index_of_refraction =
+            self.index_of_refraction.at(ray_in.wavelength());
+        if rec.front_face {
+            index_of_refraction / rec.outside_index_of_refraction
+        } else {
+            rec.outside_index_of_refraction / index_of_refraction
+        }
+    }
+}
+
+impl Material for RoughDielectric {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        let Some(alpha) = ggx_alpha(self.roughness.as_ref(), rec) else {
+            return Dielectric::new(self.index_of_refraction)
+                .scatter(ray_in, rec);
+        };
+
+        let frame = Frame::new(rec.normal);
+        let wo = frame.to_local(-ray_in.direction().normalized());
+        if wo.z() <= 0.0 {
+            return None;
+        }
+
+        Some(ScatterRecord::Sampled {
+            pdf: Box::new(RoughDielectricPdf {
+                frame,
+                wo,
+                alpha,
+                eta: self.relative_eta(ray_in, rec),
+            }),
+        })
+    }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        let Some(alpha) = ggx_alpha(self.roughness.as_ref(), rec) else {
+            return Color::ZERO;
+        };
+        let frame = Frame::new(rec.normal);
+        let wo = frame.to_local(-ray_in.direction().normalized());
+        let wi = frame.to_local(direction.normalized());
+        Color::from_float(rough_dielectric_eval(
+            wo,
+            wi,
+            alpha,
+            self.relative_eta(ray_in, rec),
+        ))
+    }
+
+    fn interior(&self) -> Option<Interior> {
+        Some(Interior {
+            index_of_refraction: self.index_of_refraction,
+            absorption: self.absorption,
+            priority: self.priority,
+        })
+    }
+
+    fn depends_on_wavelength(&self) -> bool {
+        self.index_of_refraction.is_dispersive()
+    }
+}
+
+// A clear dielectric layer over any material, like varnish, car paint or
+// plastic. The coat reflects with `Dielectric::reflectance`, smooth or GGX
+// rough, and what it lets through reaches `base`, darkened by `tint` over
+// every `thickness` travelled inside the coat. Over a base scattering into a
+// single direction, like `Metal` or `Dielectric`, the coat is always smooth
+pub struct Coated {
+    base: Arc<dyn Material>,
+    index_of_refraction: f64,
+    roughness: Arc<dyn Texture>,
+    tint: Color,
+    thickness: f64,
+}
+
+impl Coated {
+    pub fn new(
+        base: Arc<dyn Material>,
+        index_of_refraction: f64,
+        roughness: Arc<dyn Texture>,
+    ) -> Self {
+        Self {
+            base,
+            index_of_refraction,
+            roughness,
+            tint: Color::ONE,
+            thickness: 0.0,
+        }
+    }
+
+    // `tint` is the color after crossing a coat of thickness 1 head on
+    pub fn with_tint(self, tint: Color, thickness: f64) -> Self {
+        Self {
+            tint,
+            thickness,
+            ..self
+        }
+    }
+
+    fn reflectance(&self, cosine: f64) -> f64 {
+        Dielectric::reflectance(
+            cosine.clamp(0.0, 1.0),
+            self.index_of_refraction,
+        )
+    }
+
+    // What reaches the base and comes back out, `cos_i` is negative when the
+    // base sends light into the object underneath
+    fn transmittance(&self, cos_o: f64, cos_i: f64) -> Color {
+        // the path through the coat is longer at grazing angles
+        let refracted = |cosine: f64| {
+            let sin2 =
+                (1.0 - cosine * cosine) / self.index_of_refraction.powi(2);
+            (1.0 - sin2).max(1e-4).sqrt()
+        };
+
+        let mut distance = 1.0 / refracted(cos_o);
+        let mut transmittance = 1.0 - self.reflectance(cos_o);
+        if cos_i > 0.0 {
+            distance += 1.0 / refracted(cos_i);
+            transmittance *= 1.0 - self.reflectance(cos_i);
+        }
+
+        let exponent = self.thickness * distance;
+        transmittance
+            * Color::new(
+                self.tint.x().powf(exponent),
+                self.tint.y().powf(exponent),
+                self.tint.z().powf(exponent),
+            )
+    }
+}
+
+impl Material for Coated {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        let frame = Frame::new(rec.normal);
+        let wo = frame.to_local(-ray_in.direction().normalized());
+        if wo.z() <= 0.0 {
+            return None;
+        }
+
+        // reflect off the coat about as often as it does
+        let coat_probability = self.reflectance(wo.z());
+        let base = self.base.scatter(ray_in, rec);
+
+        match (ggx_alpha(self.roughness.as_ref(), rec), base) {
+            // both lobes have a density, so the integrator sees all of it
+            (Some(alpha), Some(ScatterRecord::Sampled { pdf })) => {
+                Some(ScatterRecord::Sampled {
+                    pdf: Box::new(MixturePdf::new(
+                        GgxPdf { frame, wo, alpha },
+                        pdf,
+                        coat_probability,
+                    )),
+                })
+            }
+            (Some(alpha), None) => Some(ScatterRecord::Sampled {
+                pdf: Box::new(GgxPdf { frame, wo, alpha }),
+            }),
+            // smooth, or treated so over a base scattering into a single
+            // direction, and `eval` makes up for the base being picked only
+            // some of the time
+            (_, base) => {
+                if rand::thread_rng().gen::<f64>() < coat_probability {
+                    return Some(ScatterRecord::Specular {
+                        attenuation: Color::ONE,
+                        ray: Ray::new(
+                            rec.p,
+                            ray_in.direction().reflect(rec.normal),
+                            ray_in.time(),
+                        ),
+                    });
+                }
+
+                match base? {
+                    ScatterRecord::Specular { attenuation, ray } => {
+                        let cos_i =
+                            rec.normal.dot(ray.direction().normalized());
+                        Some(ScatterRecord::Specular {
+                            attenuation: attenuation
+                                * self.transmittance(wo.z(), cos_i)
+                                / (1.0 - coat_probability),
+                            ray,
+                        })
+                    }
+                    sampled => Some(sampled),
+                }
+            }
+        }
+    }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        let frame = Frame::new(rec.normal);
+        let wo = frame.to_local(-ray_in.direction().normalized());
+        let wi = frame.to_local(direction.normalized());
+        if wo.z() <= 0.0 {
+            return Color::ZERO;
+        }
+
+        let base = self.base.eval(ray_in, rec, direction)
+            * self.transmittance(wo.z(), wi.z());
+
+        let Some(alpha) = ggx_alpha(self.roughness.as_ref(), rec) else {
+            // only sampled after passing the smooth coat
+            return base / (1.0 - self.reflectance(wo.z()));
+        };
+        if wi.z() <= 0.0 {
+            return base;
+        }
+
+        // F D G2 / (4 cos_o cos_i), times cos_i
+        let h = (wo + wi).normalized();
+        let g2 =
+            1.0 / (1.0 + smith_lambda(wo, alpha) + smith_lambda(wi, alpha));
+        base + Color::from_float(
+            self.reflectance(wo.dot(h)) * ggx_d(h, alpha) * g2 / (4.0 * wo.z()),
+        )
+    }
+
+    fn color_emitted(&self, u: f64, v: f64, p: &Point3) -> Color {
+        self.base.color_emitted(u, v, p)
+    }
+
+    fn depends_on_wavelength(&self) -> bool {
+        self.base.depends_on_wavelength()
     }
 }
 
@@ -132,9 +813,7 @@ impl DiffuseLight {
     }
 
     pub fn from_color(color: Color) -> Self {
-        DiffuseLight {
-            emit: Arc::new(SolidColor::new(color)),
-        }
+        Self::new(Arc::new(SolidColor::new(color)))
     }
 }
 
@@ -143,7 +822,7 @@ impl Material for DiffuseLight {
         &self,
         _ray_in: &Ray,
         _recc: &HitRecord,
-    ) -> Option<(Color, Ray)> {
+    ) -> Option<ScatterRecord> {
         None
     }
 
@@ -160,20 +839,889 @@ impl Isotropic {
     pub fn new(albedo: Arc<dyn Texture>) -> Self {
         Self { albedo }
     }
+}
 
-    pub fn from_color(color: Color) -> Self {
+impl Material for Isotropic {
+    fn scatter(
+        &self,
+        _ray_in: &Ray,
+        _rec: &HitRecord,
+    ) -> Option<ScatterRecord> {
+        Some(ScatterRecord::Sampled {
+            pdf: Box::new(SpherePdf),
+        })
+    }
+
+    // the phase function, there is no surface to take a cosine with
+    fn eval(&self, _ray_in: &Ray, rec: &HitRecord, _direction: &Vec3) -> Color {
+        self.albedo.value_at(rec) / (4.0 * PI)
+    }
+}
+
+// An uber-material after Burley's "Physically Based Shading at Disney": a
+// diffuse base with subsurface and sheen, a GGX specular lobe tinted by the
+// base color of metals, a rough dielectric lobe for transmission and a
+// clearcoat on top. Every parameter is a texture, the scalar ones read as the
+// average of their channels. Start from `Principled::new` and override the
+// fields
+pub struct Principled {
+    pub base_color: Arc<dyn Texture>,
+    pub metallic: Arc<dyn Texture>,
+    pub roughness: Arc<dyn Texture>,
+    // reflectance of the dielectric part, 0.5 is 4% head on like an index of
+    // refraction of 1.5, which transmission also uses
+    pub specular: Arc<dyn Texture>,
+    // color of the grazing retro-reflection of cloth
+    pub sheen: Arc<dyn Texture>,
+    pub clearcoat: Arc<dyn Texture>,
+    pub clearcoat_roughness: Arc<dyn Texture>,
+    pub transmission: Arc<dyn Texture>,
+    // flattens the diffuse lobe like light scattered under the surface
+    pub subsurface: Arc<dyn Texture>,
+    pub emission: Arc<dyn Texture>,
+}
+
+impl Principled {
+    // an opaque, somewhat rough dielectric
+    pub fn new(base_color: Arc<dyn Texture>) -> Self {
+        let value = |value: f64| -> Arc<dyn Texture> {
+            Arc::new(SolidColor::new(Color::from_float(value)))
+        };
         Self {
-            albedo: Arc::new(SolidColor::new(color)),
+            base_color,
+            metallic: value(0.0),
+            roughness: value(0.5),
+            specular: value(0.5),
+            sheen: value(0.0),
+            clearcoat: value(0.0),
+            clearcoat_roughness: value(0.03),
+            transmission: value(0.0),
+            subsurface: value(0.0),
+            emission: value(0.0),
         }
     }
 }
 
-impl Material for Isotropic {
-    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
-        let scattered =
-            Ray::new(rec.p, Vec3::random_in_unit_sphere(), ray_in.time());
+// Everything about the lobes that only depends on the hit and the incoming ray
+struct PrincipledLobes {
+    frame: Frame,
+    wo: Vec3,
+    // inside a transmissive object only the interface is left to scatter off
+    inside: bool,
+    base_color: Color,
+    roughness: f64,
+    subsurface: f64,
+    sheen: Color,
+    alpha: f64,
+    f0: Color,
+    // the dielectric part reflects this much head on
+    dielectric_f0: f64,
+    // relative index of refraction across the surface
+    eta: f64,
+    clearcoat: f64,
+    clearcoat_alpha: f64,
+    // what the clearcoat lets through to the layers below
+    base_weight: f64,
+    diffuse_weight: f64,
+    transmission_weight: f64,
+    // of sampling the diffuse, specular, clearcoat and transmission lobes
+    probabilities: [f64; 4],
+}
+
+impl Principled {
+    fn lobes(&self, ray_in: &Ray, rec: &HitRecord) -> Option<PrincipledLobes> {
+        let scalar = |texture: &Arc<dyn Texture>| {
+            average(texture.value_at(rec)).clamp(0.0, 1.0)
+        };
+
+        let base_color = self.base_color.value_at(rec);
+        let metallic = scalar(&self.metallic);
+        // the lobes are sampled, too smooth and GGX gets unstable
+        let roughness = scalar(&self.roughness).max(SMOOTH_ROUGHNESS);
+        let clearcoat_roughness =
+            scalar(&self.clearcoat_roughness).max(SMOOTH_ROUGHNESS);
+        let transmission = scalar(&self.transmission);
+        let clearcoat = scalar(&self.clearcoat);
+
+        let frame = Frame::new(rec.normal);
+        let wo = frame.to_local(-ray_in.direction().normalized());
+        if wo.z() <= 0.0 {
+            return None;
+        }
+
+        let dielectric_f0 = (0.08 * scalar(&self.specular)).max(1e-4);
+        let ior = (1.0 + dielectric_f0.sqrt()) / (1.0 - dielectric_f0.sqrt());
+        let f0 = (1.0 - metallic) * Color::from_float(dielectric_f0)
+            + metallic * base_color;
+        let sheen = self.sheen.value_at(rec);
+
+        let inside = !rec.front_face && transmission > 0.0;
+        let base_weight = 1.0 - clearcoat * schlick_scalar(0.04, wo.z());
+        let diffuse_weight = (1.0 - metallic) * (1.0 - transmission);
+        let transmission_weight = (1.0 - metallic) * transmission;
+
+        // pick a lobe proportionally to its expected contribution
+        let probabilities = if inside {
+            [0.0, 0.0, 0.0, 1.0]
+        } else {
+            let diffuse = average(base_color)
+                * (1.0 - schlick_scalar(dielectric_f0, wo.z()))
+                + average(sheen);
+            [
+                base_weight * diffuse_weight * diffuse,
+                base_weight
+                    * (1.0 - transmission_weight)
+                    * average(schlick(f0, wo.z())),
+                clearcoat * schlick_scalar(0.04, wo.z()),
+                base_weight * transmission_weight,
+            ]
+        };
+        let total: f64 = probabilities.iter().sum();
+        if total <= 0.0 {
+            return None;
+        }
+
+        Some(PrincipledLobes {
+            frame,
+            wo,
+            inside,
+            base_color,
+            roughness,
+            subsurface: scalar(&self.subsurface),
+            sheen,
+            alpha: roughness * roughness,
+            f0,
+            dielectric_f0,
+            eta: if rec.front_face { ior } else { 1.0 / ior },
+            clearcoat,
+            clearcoat_alpha: clearcoat_roughness * clearcoat_roughness,
+            base_weight,
+            diffuse_weight,
+            transmission_weight,
+            probabilities: probabilities.map(|p| p / total),
+        })
+    }
+}
+
+impl Material for Principled {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        let lobes = self.lobes(ray_in, rec)?;
+        let (frame, wo) = (lobes.frame, lobes.wo);
+        let [diffuse, specular, clearcoat, transmission] = lobes.probabilities;
+
+        Some(ScatterRecord::Sampled {
+            pdf: mixture(vec![
+                (diffuse, Box::new(CosinePdf::new(rec.normal))),
+                (
+                    specular,
+                    Box::new(GgxPdf {
+                        frame,
+                        wo,
+                        alpha: lobes.alpha,
+                    }),
+                ),
+                (
+                    clearcoat,
+                    Box::new(GgxPdf {
+                        frame,
+                        wo,
+                        alpha: lobes.clearcoat_alpha,
+                    }),
+                ),
+                (
+                    transmission,
+                    Box::new(RoughDielectricPdf {
+                        frame,
+                        wo,
+                        alpha: lobes.alpha,
+                        eta: lobes.eta,
+                    }),
+                ),
+            ]),
+        })
+    }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        let Some(lobes) = self.lobes(ray_in, rec) else {
+            return Color::ZERO;
+        };
+        let wo = lobes.wo;
+        let wi = lobes.frame.to_local(direction.normalized());
+
+        let interface = rough_dielectric_eval(wo, wi, lobes.alpha, lobes.eta);
+        if lobes.inside {
+            return Color::from_float(interface);
+        }
+
+        // the base color tints what refracts into the object
+        let tint = if wi.z() < 0.0 { lobes.base_color } else { Color::ONE };
+        let mut value =
+            lobes.base_weight * lobes.transmission_weight * interface * tint;
+        if wi.z() <= 0.0 {
+            return value;
+        }
+
+        let h = (wo + wi).normalized();
+        let cos_d = wi.dot(h);
+
+        // Burley's diffuse with its retro-reflection, blended with the
+        // Hanrahan-Krueger like flattening of subsurface scattering
+        let fl = (1.0 - wi.z()).powi(5);
+        let fv = (1.0 - wo.z()).powi(5);
+        let rr = lobes.roughness * cos_d * cos_d;
+        let fd90 = 0.5 + 2.0 * rr;
+        let fd = (1.0 + (fd90 - 1.0) * fl) * (1.0 + (fd90 - 1.0) * fv);
+        let fss = (1.0 + (rr - 1.0) * fl) * (1.0 + (rr - 1.0) * fv);
+        let ss = 1.25 * (fss * (1.0 / (wi.z() + wo.z()) - 0.5) + 0.5);
+        let diffuse = lobes.base_color / PI
+            * ((1.0 - lobes.subsurface) * fd + lobes.subsurface * ss)
+            * (1.0 - schlick_scalar(lobes.dielectric_f0, wo.z()))
+            + lobes.sheen * (1.0 - cos_d).powi(5);
+        value += lobes.base_weight * lobes.diffuse_weight * wi.z() * diffuse;
+
+        // F D G2 / (4 cos_o cos_i), times cos_i
+        let g2 = |alpha| {
+            1.0 / (1.0 + smith_lambda(wo, alpha) + smith_lambda(wi, alpha))
+        };
+        let specular = schlick(lobes.f0, wo.dot(h))
+            * (ggx_d(h, lobes.alpha) * g2(lobes.alpha) / (4.0 * wo.z()));
+        value +=
+            lobes.base_weight * (1.0 - lobes.transmission_weight) * specular;
+
+        let clearcoat = schlick_scalar(0.04, wo.dot(h))
+            * ggx_d(h, lobes.clearcoat_alpha)
+            * g2(lobes.clearcoat_alpha)
+            / (4.0 * wo.z());
+        value + Color::from_float(lobes.clearcoat * clearcoat)
+    }
+
+    fn color_emitted(&self, u: f64, v: f64, p: &Point3) -> Color {
+        self.emission.value(u, v, p)
+    }
+}
+
+// Picks each pdf with its probability, which add up to 1
+fn mixture(lobes: Vec<(f64, Box<dyn Pdf>)>) -> Box<dyn Pdf> {
+    let mut lobes = lobes.into_iter().filter(|(p, _)| *p > 0.0).rev();
+    let (mut total, mut pdf) = lobes.next().expect("a lobe to sample");
+    for (p, lobe) in lobes {
+        total += p;
+        pdf = Box::new(MixturePdf::new(lobe, pdf, p / total));
+    }
+    pdf
+}
+
+#[derive(Clone, Copy)]
+struct Frame {
+    t: Vec3,
+    b: Vec3,
+    n: Vec3,
+}
+
+impl Frame {
+    fn new(n: Vec3) -> Self {
+        let (t, b) = n.orthonormal_basis();
+        Self { t, b, n }
+    }
+
+    fn to_local(self, v: Vec3) -> Vec3 {
+        Vec3::new(v.dot(self.t), v.dot(self.b), v.dot(self.n))
+    }
+
+    fn to_world(self, v: Vec3) -> Vec3 {
+        v.x() * self.t + v.y() * self.b + v.z() * self.n
+    }
+}
+
+// Reflections off GGX visible normals seen from `wo`
+struct GgxPdf {
+    frame: Frame,
+    wo: Vec3,
+    alpha: f64,
+}
+
+impl Pdf for GgxPdf {
+    fn value(&self, direction: &Vec3) -> f64 {
+        let wi = self.frame.to_local(direction.normalized());
+        if wi.z() <= 0.0 {
+            return 0.0;
+        }
+
+        // the visible normal density G1 D (wo.h) / wo.z over the 4 (wo.h)
+        // Jacobian of reflecting
+        let h = (self.wo + wi).normalized();
+        let g1 = 1.0 / (1.0 + smith_lambda(self.wo, self.alpha));
+        g1 * ggx_d(h, self.alpha) / (4.0 * self.wo.z())
+    }
+
+    fn generate(&self) -> Vec3 {
+        let mut rng = rand::thread_rng();
+        let h = sample_ggx_vndf(self.wo, self.alpha, rng.gen(), rng.gen());
+        self.frame.to_world(2.0 * self.wo.dot(h) * h - self.wo)
+    }
+}
+
+// The mirror direction moved by a random point in a ball of radius `fuzz`
+struct FuzzPdf {
+    reflected: Vec3,
+    fuzz: f64,
+}
+
+impl Pdf for FuzzPdf {
+    // the ball's uniform density integrated over the chord the direction cuts
+    // through it, t² dt from the near to the far end
+    fn value(&self, direction: &Vec3) -> f64 {
+        let cosine = self.reflected.dot(direction.normalized());
+        let sin2 = 1.0 - cosine * cosine;
+        let fuzz2 = self.fuzz * self.fuzz;
+        if sin2 >= fuzz2 {
+            return 0.0;
+        }
+
+        let half_chord = (fuzz2 - sin2).sqrt();
+        let far = cosine + half_chord;
+        if far <= 0.0 {
+            return 0.0;
+        }
+        let near = (cosine - half_chord).max(0.0);
+        (far.powi(3) - near.powi(3)) / (4.0 * PI * fuzz2 * self.fuzz)
+    }
+
+    fn generate(&self) -> Vec3 {
+        self.reflected + self.fuzz * Vec3::random_in_unit_sphere()
+    }
+}
+
+// Reflection or refraction off GGX visible normals seen from `wo`, as picked
+// by the Fresnel term. `eta` is relative to the side of `wo`
+struct RoughDielectricPdf {
+    frame: Frame,
+    wo: Vec3,
+    alpha: f64,
+    eta: f64,
+}
+
+impl RoughDielectricPdf {
+    // density of the visible normal `h`
+    fn vndf(&self, h: Vec3) -> f64 {
+        let g1 = 1.0 / (1.0 + smith_lambda(self.wo, self.alpha));
+        g1 * ggx_d(h, self.alpha) * self.wo.dot(h).max(0.0) / self.wo.z()
+    }
+}
+
+impl Pdf for RoughDielectricPdf {
+    // Either lobe can land on either side of the surface at grazing angles,
+    // so both are counted wherever `direction` is
+    fn value(&self, direction: &Vec3) -> f64 {
+        let wo = self.wo;
+        let wi = self.frame.to_local(direction.normalized());
+        let mut pdf = 0.0;
+
+        if let Some(h) = dielectric_half_vector(wo, wi, 1.0) {
+            let fresnel = fresnel_dielectric(wo.dot(h), self.eta);
+            pdf += fresnel * self.vndf(h) / (4.0 * wo.dot(h));
+        }
+        if let Some(h) = dielectric_half_vector(wo, wi, self.eta) {
+            if wi.dot(h) < 0.0 {
+                let fresnel = fresnel_dielectric(wo.dot(h), self.eta);
+                let denom = (wi.dot(h) + wo.dot(h) / self.eta).powi(2);
+                pdf += (1.0 - fresnel) * self.vndf(h) * -wi.dot(h) / denom;
+            }
+        }
+        pdf
+    }
+
+    fn generate(&self) -> Vec3 {
+        let mut rng = rand::thread_rng();
+        let wo = self.wo;
+        let h = sample_ggx_vndf(wo, self.alpha, rng.gen(), rng.gen());
+
+        let cos_i = wo.dot(h);
+        let sin2_t = (1.0 - cos_i * cos_i) / (self.eta * self.eta);
+        let wi = if sin2_t >= 1.0
+            || rng.gen::<f64>() < fresnel_dielectric(cos_i, self.eta)
+        {
+            2.0 * cos_i * h - wo
+        } else {
+            let cos_t = (1.0 - sin2_t).sqrt();
+            -wo / self.eta + (cos_i / self.eta - cos_t) * h
+        };
+        self.frame.to_world(wi)
+    }
+}
+
+// The microfacet normal between `wo` and `wi` for a change of index `eta`
+// from the side of `wo` to the side of `wi`, 1 for reflections. None unless
+// `wo` sees its front
+fn dielectric_half_vector(wo: Vec3, wi: Vec3, eta: f64) -> Option<Vec3> {
+    let h = eta * wi + wo;
+    if h.length_squared() <= 0.0 {
+        return None;
+    }
+    let h = h.normalized();
+    let h = if h.z() < 0.0 { -h } else { h };
+    (wo.dot(h) > 0.0).then_some(h)
+}
 
-        let attenuation = self.albedo.value(rec.u, rec.v, &rec.p);
-        Some((attenuation, scattered))
+// BSDF times the cosine of rough glass, `eta` relative to the side of `wo`
+fn rough_dielectric_eval(wo: Vec3, wi: Vec3, alpha: f64, eta: f64) -> f64 {
+    if wo.z() <= 0.0 || wi.z() == 0.0 {
+        return 0.0;
+    }
+
+    let reflected = wi.z() > 0.0;
+    let Some(h) =
+        dielectric_half_vector(wo, wi, if reflected { 1.0 } else { eta })
+    else {
+        return 0.0;
+    };
+    // refracting through the back of the microfacet
+    if !reflected && wi.dot(h) >= 0.0 {
+        return 0.0;
+    }
+
+    let fresnel = fresnel_dielectric(wo.dot(h), eta);
+    let d = ggx_d(h, alpha);
+    let g2 = 1.0 / (1.0 + smith_lambda(wo, alpha) + smith_lambda(wi, alpha));
+    if reflected {
+        fresnel * d * g2 / (4.0 * wo.z())
+    } else {
+        // Walter et al. 2007. Like `Dielectric` this leaves out the 1 / eta^2
+        // scaling of radiance, which cancels out going in and out of closed
+        // objects
+        let denom = (wi.dot(h) + wo.dot(h) / eta).powi(2);
+        (1.0 - fresnel) * d * g2 * (wi.dot(h) * wo.dot(h)).abs()
+            / (wo.z() * denom)
+    }
+}
+
+// Unpolarized reflectance off a dielectric, `eta` relative to the incident
+// side
+fn fresnel_dielectric(cos_i: f64, eta: f64) -> f64 {
+    let cos_i = cos_i.clamp(0.0, 1.0);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let r_parallel = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let r_perpendicular = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    (r_parallel * r_parallel + r_perpendicular * r_perpendicular) / 2.0
+}
+
+// Unpolarized reflectance off a conductor of complex index `eta` + i `k`,
+// coming from air
+fn fresnel_conductor(cos_i: f64, eta: f64, k: f64) -> f64 {
+    let cos2 = cos_i.clamp(0.0, 1.0).powi(2);
+    let sin2 = 1.0 - cos2;
+    let (eta2, k2) = (eta * eta, k * k);
+
+    let t0 = eta2 - k2 - sin2;
+    let a2_plus_b2 = (t0 * t0 + 4.0 * eta2 * k2).sqrt();
+    let t1 = a2_plus_b2 + cos2;
+    let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+    let t2 = 2.0 * cos2.sqrt() * a;
+    let r_s = (t1 - t2) / (t1 + t2);
+
+    let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let r_p = r_s * (t3 - t4) / (t3 + t4);
+
+    (r_s + r_p) / 2.0
+}
+
+// Reflectance of a film of index `film` and `thickness` nm over a substrate
+// of complex index `substrate`, lit at `cos_i` from a medium of index
+// `outside`: Airy's sum of the reflections bouncing inside the film, averaged
+// over the two polarizations
+fn thin_film_reflectance(
+    cos_i: f64,
+    wavelength: f64,
+    thickness: f64,
+    outside: f64,
+    film: f64,
+    substrate: Complex,
+) -> f64 {
+    let (n0, n1, n2) = (Complex::real(outside), Complex::real(film), substrate);
+    // Snell's law, complex past total internal reflection or in a conductor
+    let sin2 = (1.0 - cos_i * cos_i) * outside * outside;
+    let cosine_in = |n: Complex| {
+        (Complex::real(1.0) - Complex::real(sin2) / (n * n)).sqrt()
+    };
+    let (cos0, cos1, cos2) =
+        (Complex::real(cos_i), cosine_in(n1), cosine_in(n2));
+
+    // phase between two successive reflections out of the film
+    let delay =
+        (Complex::new(0.0, 4.0 * PI * thickness / wavelength) * n1 * cos1)
+            .exp();
+    let airy = |r01: Complex, r12: Complex| {
+        ((r01 + r12 * delay) / (Complex::real(1.0) + r01 * r12 * delay))
+            .norm_sqr()
+    };
+
+    let s = airy(
+        (n0 * cos0 - n1 * cos1) / (n0 * cos0 + n1 * cos1),
+        (n1 * cos1 - n2 * cos2) / (n1 * cos1 + n2 * cos2),
+    );
+    let p = airy(
+        (n1 * cos0 - n0 * cos1) / (n1 * cos0 + n0 * cos1),
+        (n2 * cos1 - n1 * cos2) / (n2 * cos1 + n1 * cos2),
+    );
+    ((s + p) / 2.0).min(1.0)
+}
+
+// A channel of a color measured at about 650, 550 and 450 nm, like the
+// indices of `RoughConductor`, in between at other wavelengths
+fn at_wavelength(color: Color, wavelength: f64) -> f64 {
+    if wavelength < 550.0 {
+        let t = ((wavelength - 450.0) / 100.0).clamp(0.0, 1.0);
+        color.z() + t * (color.y() - color.z())
+    } else {
+        let t = ((wavelength - 550.0) / 100.0).clamp(0.0, 1.0);
+        color.y() + t * (color.x() - color.y())
+    }
+}
+
+// A conductor's complex index from its reflectance head on and towards the
+// edges (Gulbrandsen, "Artist Friendly Metallic Fresnel", 2014)
+fn conductor_index(reflectivity: f64, edge_tint: f64) -> Complex {
+    let r = reflectivity.clamp(0.0, 0.99);
+    let sqrt_r = r.sqrt();
+    let n = edge_tint * (1.0 - r) / (1.0 + r)
+        + (1.0 - edge_tint) * (1.0 + sqrt_r) / (1.0 - sqrt_r);
+    let k2 = (r * (n + 1.0).powi(2) - (n - 1.0).powi(2)) / (1.0 - r);
+    Complex::new(n, k2.max(0.0).sqrt())
+}
+
+// Just enough complex arithmetic for thin films
+#[derive(Clone, Copy, Debug)]
+struct Complex {
+    re: f64,
+    im: f64,
+}
+
+impl Complex {
+    fn new(re: f64, im: f64) -> Self {
+        Self { re, im }
+    }
+
+    fn real(re: f64) -> Self {
+        Self::new(re, 0.0)
+    }
+
+    fn norm_sqr(self) -> f64 {
+        self.re * self.re + self.im * self.im
+    }
+
+    // the root with a positive real part, or imaginary part for negative
+    // reals, so that waves fade into a conductor or past total reflection
+    fn sqrt(self) -> Self {
+        let norm = self.norm_sqr().sqrt();
+        let re = ((norm + self.re) / 2.0).max(0.0).sqrt();
+        let im = ((norm - self.re) / 2.0).max(0.0).sqrt();
+        Self::new(re, if self.im < 0.0 { -im } else { im })
+    }
+
+    fn exp(self) -> Self {
+        let magnitude = self.re.exp();
+        Self::new(magnitude * self.im.cos(), magnitude * self.im.sin())
+    }
+}
+
+impl Add for Complex {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self::new(self.re + other.re, self.im + other.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Self::new(self.re - other.re, self.im - other.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        Self::new(
+            self.re * other.re - self.im * other.im,
+            self.re * other.im + self.im * other.re,
+        )
+    }
+}
+
+impl Div for Complex {
+    type Output = Self;
+
+    fn div(self, other: Self) -> Self {
+        let norm = other.norm_sqr();
+        Self::new(
+            (self.re * other.re + self.im * other.im) / norm,
+            (self.im * other.re - self.re * other.im) / norm,
+        )
+    }
+}
+
+// Below this roughness GGX gets numerically unstable, so surfaces are treated
+// as perfectly smooth
+const SMOOTH_ROUGHNESS: f64 = 0.02;
+
+// GGX alpha of a roughness texture at the hit, None when smooth
+fn ggx_alpha(roughness: &dyn Texture, rec: &HitRecord) -> Option<f64> {
+    let roughness = average(roughness.value_at(rec)).min(1.0);
+    (roughness >= SMOOTH_ROUGHNESS).then_some(roughness * roughness)
+}
+
+// GGX normal distribution, `h` in the local frame around the normal
+fn ggx_d(h: Vec3, alpha: f64) -> f64 {
+    let alpha2 = alpha * alpha;
+    let d = h.z() * h.z() * (alpha2 - 1.0) + 1.0;
+    alpha2 / (PI * d * d)
+}
+
+fn schlick(f0: Color, cosine: f64) -> Color {
+    f0 + (Color::ONE - f0) * (1.0 - cosine.clamp(0.0, 1.0)).powi(5)
+}
+
+fn schlick_scalar(f0: f64, cosine: f64) -> f64 {
+    f0 + (1.0 - f0) * (1.0 - cosine.clamp(0.0, 1.0)).powi(5)
+}
+
+fn average(c: Color) -> f64 {
+    (c.x() + c.y() + c.z()) / 3.0
+}
+
+// Smith masking for GGX, `w` in the local frame around the normal
+fn smith_lambda(w: Vec3, alpha: f64) -> f64 {
+    let cos2 = w.z() * w.z();
+    let tan2 = (1.0 - cos2).max(0.0) / cos2;
+    ((1.0 + alpha * alpha * tan2).sqrt() - 1.0) / 2.0
+}
+
+// Heitz 2018, "Sampling the GGX Distribution of Visible Normals"
+fn sample_ggx_vndf(wo: Vec3, alpha: f64, u1: f64, u2: f64) -> Vec3 {
+    let vh = Vec3::new(alpha * wo.x(), alpha * wo.y(), wo.z()).normalized();
+
+    let len2 = vh.x() * vh.x() + vh.y() * vh.y();
+    let t1 = if len2 > 0.0 {
+        Vec3::new(-vh.y(), vh.x(), 0.0) / len2.sqrt()
+    } else {
+        Vec3::from_x(1.0)
+    };
+    let t2 = vh.cross(t1);
+
+    let r = u1.sqrt();
+    let phi = 2.0 * PI * u2;
+    let p1 = r * phi.cos();
+    let s = 0.5 * (1.0 + vh.z());
+    let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+
+    let nh = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * vh;
+    Vec3::new(alpha * nh.x(), alpha * nh.y(), nh.z().max(0.0)).normalized()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::integral;
+
+    // BRDF of light arriving along `wi` and leaving along `wo`, on a surface
+    // facing +y
+    fn brdf(material: Arc<dyn Material>, wo: Vec3, wi: Vec3) -> Color {
+        let mut rec = HitRecord::default(material.clone());
+        rec.normal = Vec3::from_y(1.0);
+        rec.front_face = true;
+        let ray_in = Ray::new(wo, -wo, 0.0);
+        material.eval(&ray_in, &rec, &wi) / wi.normalized().y()
+    }
+
+    #[test]
+    fn oren_nayar_is_reciprocal_and_lambertian_when_smooth() {
+        let albedo = Color::new(0.8, 0.5, 0.2);
+        let lambertian: Arc<dyn Material> =
+            Arc::new(Lambertian::from_solid_color(albedo));
+        let smooth: Arc<dyn Material> =
+            Arc::new(OrenNayar::new(Arc::new(SolidColor::new(albedo)), 0.0));
+        let rough: Arc<dyn Material> =
+            Arc::new(OrenNayar::new(Arc::new(SolidColor::new(albedo)), 30.0));
+
+        for _ in 0..100 {
+            let wo = Vec3::random_in_hemisphere(Vec3::from_y(1.0)).normalized();
+            let wi = Vec3::random_in_hemisphere(Vec3::from_y(1.0)).normalized();
+            let difference =
+                brdf(smooth.clone(), wo, wi) - brdf(lambertian.clone(), wo, wi);
+            assert!(difference.length() < 1e-9);
+            let difference =
+                brdf(rough.clone(), wo, wi) - brdf(rough.clone(), wi, wo);
+            assert!(difference.length() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn fuzzy_metal_density_integrates_to_one() {
+        let reflected = Vec3::new(0.3, 1.0, -0.2).normalized();
+        for fuzz in [0.3, 0.6, 1.0, 1.5] {
+            let pdf = FuzzPdf { reflected, fuzz };
+            let integral = integral(&pdf);
+            assert!((integral - 1.0).abs() < 0.05, "integral {}", integral);
+
+            for _ in 0..1000 {
+                assert!(pdf.value(&pdf.generate()) > 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn rough_dielectric_density_integrates_to_one() {
+        let frame = Frame::new(Vec3::new(0.3, 1.0, -0.2).normalized());
+        for (alpha, eta, cos_o) in
+            [(0.5, 1.5, 0.8_f64), (0.7, 1.0 / 1.5, 0.9), (0.4, 1.33, 0.3)]
+        {
+            let wo = Vec3::new((1.0 - cos_o * cos_o).sqrt(), 0.0, cos_o);
+            let pdf = RoughDielectricPdf {
+                frame,
+                wo,
+                alpha,
+                eta,
+            };
+            let integral = integral(&pdf);
+            assert!((integral - 1.0).abs() < 0.05, "integral {}", integral);
+
+            for _ in 0..1000 {
+                assert!(pdf.value(&pdf.generate()) > 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn coat_over_white_loses_little_and_creates_nothing() {
+        let white: Arc<dyn Material> =
+            Arc::new(Lambertian::from_solid_color(Color::ONE));
+        let coated: Arc<dyn Material> = Arc::new(Coated::new(
+            white,
+            1.5,
+            Arc::new(SolidColor::new(Color::from_float(0.3))),
+        ));
+
+        // uniform over the hemisphere, eval already has the cosine
+        let wo = Vec3::new(0.6, 0.8, 0.0);
+        let n = 200_000;
+        let albedo = (0..n)
+            .map(|_| {
+                let wi =
+                    Vec3::random_in_hemisphere(Vec3::from_y(1.0)).normalized();
+                brdf(coated.clone(), wo, wi).x() * wi.y()
+            })
+            .sum::<f64>()
+            * 2.0
+            * PI
+            / n as f64;
+        assert!(albedo > 0.8 && albedo < 1.0, "albedo {}", albedo);
+    }
+
+    #[test]
+    fn principled_passes_the_white_furnace() {
+        let value = |value: f64| -> Arc<dyn Texture> {
+            Arc::new(SolidColor::new(Color::from_float(value)))
+        };
+        let white = Principled::new(value(1.0));
+        let mut rec = HitRecord::default(Arc::new(white));
+        rec.normal = Vec3::from_y(1.0);
+        rec.front_face = true;
+
+        for (metallic, transmission, clearcoat) in [
+            (0.0, 0.0, 0.0),
+            (1.0, 0.0, 0.0),
+            (0.0, 1.0, 0.0),
+            (0.0, 0.0, 1.0),
+            (0.5, 0.5, 1.0),
+        ] {
+            for roughness in [0.2, 1.0] {
+                let mut material = Principled::new(value(1.0));
+                material.metallic = value(metallic);
+                material.transmission = value(transmission);
+                material.clearcoat = value(clearcoat);
+                material.roughness = value(roughness);
+
+                for wo in [Vec3::from_y(1.0), Vec3::new(0.8, 0.6, 0.0)] {
+                    let ray_in = Ray::new(wo, -wo, 0.0);
+                    let Some(ScatterRecord::Sampled { pdf }) =
+                        material.scatter(&ray_in, &rec)
+                    else {
+                        panic!("a principled surface scatters by sampling");
+                    };
+
+                    let n = 50_000;
+                    let mut albedo = 0.0;
+                    for _ in 0..n {
+                        let wi = pdf.generate();
+                        let density = pdf.value(&wi);
+                        assert!(density >= 0.0, "density {}", density);
+                        if density > 0.0 {
+                            albedo +=
+                                material.eval(&ray_in, &rec, &wi).x() / density;
+                        }
+                    }
+                    albedo /= n as f64;
+                    // Burley's rough diffuse retro-reflects a little more
+                    // than it receives head on
+                    assert!(
+                        albedo > 0.0 && albedo < 1.01,
+                        "albedo {} with metallic {}, transmission {}, \
+                         clearcoat {}, roughness {}",
+                        albedo,
+                        metallic,
+                        transmission,
+                        clearcoat,
+                        roughness
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn film_without_thickness_is_plain_fresnel() {
+        for cos_i in [1.0, 0.7, 0.3, 0.05] {
+            let glass = thin_film_reflectance(
+                cos_i,
+                550.0,
+                0.0,
+                1.0,
+                1.33,
+                Complex::real(1.5),
+            );
+            assert!((glass - fresnel_dielectric(cos_i, 1.5)).abs() < 1e-9);
+
+            let gold = thin_film_reflectance(
+                cos_i,
+                550.0,
+                0.0,
+                1.0,
+                1.33,
+                Complex::new(0.374, 2.385),
+            );
+            assert!(
+                (gold - fresnel_conductor(cos_i, 0.374, 2.385)).abs() < 1e-9
+            );
+        }
+
+        // a quarter wave coating on glass takes most of the reflection away
+        let coated = thin_film_reflectance(
+            1.0,
+            550.0,
+            550.0 / (4.0 * 1.38),
+            1.0,
+            1.38,
+            Complex::real(1.5),
+        );
+        assert!(coated < 0.5 * fresnel_dielectric(1.0, 1.5));
     }
 }
@@ -81,6 +81,8 @@ impl Hittable for MovingSphere {
             normal: Vec3::ZERO,
             mat: self.material.clone(),
             front_face: false,
+            outside_index_of_refraction: 1.0,
+            vertex_color: None,
         };
         let outward_normal = (rec.p - self.center(ray.time())) / self.radius;
         rec.set_face_normal(ray, outward_normal);
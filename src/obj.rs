@@ -0,0 +1,269 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt::{self, Display},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use image::ImageError;
+
+use crate::{
+    hit::World,
+    material::{Dielectric, DiffuseLight, Lambertian, Material, Metal},
+    texture::{ImageTexture, SolidColor, Texture},
+    triangle::{MeshError, TriangleMesh},
+    vec3::{Color, Point3, Vec3},
+};
+
+#[derive(Debug)]
+pub enum ObjError {
+    Obj(PathBuf, tobj::LoadError),
+    Mtl(PathBuf, tobj::LoadError),
+    Texture(PathBuf, ImageError),
+    Mesh(PathBuf, MeshError),
+    Empty(PathBuf),
+}
+
+impl Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObjError::Obj(path, err) => {
+                write!(f, "{}: {}", path.display(), err)
+            }
+            ObjError::Mtl(path, err) => {
+                write!(f, "{}: material library: {}", path.display(), err)
+            }
+            ObjError::Texture(path, err) => {
+                write!(f, "{}: texture: {}", path.display(), err)
+            }
+            ObjError::Mesh(path, err) => {
+                write!(f, "{}: {}", path.display(), err)
+            }
+            ObjError::Empty(path) => {
+                write!(f, "{}: no triangles", path.display())
+            }
+        }
+    }
+}
+
+impl Error for ObjError {}
+
+// Loads every object and group of an OBJ file as a triangle mesh. Faces
+// without an MTL material use `default_material`.
+pub fn load(
+    path: &Path,
+    default_material: Arc<dyn Material>,
+) -> Result<World, ObjError> {
+    let (models, materials) = tobj::load_obj(path, &tobj::GPU_LOAD_OPTIONS)
+        .map_err(|err| ObjError::Obj(path.to_path_buf(), err))?;
+    let materials =
+        materials.map_err(|err| ObjError::Mtl(path.to_path_buf(), err))?;
+
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let mut textures = HashMap::new();
+    let materials = materials
+        .iter()
+        .map(|material| mtl_material(material, dir, &mut textures))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let world: World = models
+        .into_iter()
+        .filter(|model| !model.mesh.indices.is_empty())
+        .map(|model| {
+            let mesh = model.mesh;
+            let vertex_count = mesh.positions.len() / 3;
+
+            let positions = mesh
+                .positions
+                .chunks_exact(3)
+                .map(|p| Point3::new(p[0] as f64, p[1] as f64, p[2] as f64))
+                .collect();
+            let normals = (mesh.normals.len() == 3 * vertex_count).then(|| {
+                mesh.normals
+                    .chunks_exact(3)
+                    .map(|n| Vec3::new(n[0] as f64, n[1] as f64, n[2] as f64))
+                    .collect()
+            });
+            let uvs = (mesh.texcoords.len() == 2 * vertex_count).then(|| {
+                mesh.texcoords
+                    .chunks_exact(2)
+                    .map(|uv| (uv[0] as f64, uv[1] as f64))
+                    .collect()
+            });
+            let indices = mesh
+                .indices
+                .chunks_exact(3)
+                .map(|i| [i[0] as usize, i[1] as usize, i[2] as usize])
+                .collect();
+            let material = mesh
+                .material_id
+                .and_then(|id| materials.get(id).cloned())
+                .unwrap_or_else(|| default_material.clone());
+
+            TriangleMesh::new(positions, normals, uvs, None, indices, material)
+                .map(|mesh| Box::new(mesh) as _)
+                .map_err(|err| ObjError::Mesh(path.to_path_buf(), err))
+        })
+        .collect::<Result<_, _>>()?;
+
+    if world.is_empty() {
+        return Err(ObjError::Empty(path.to_path_buf()));
+    }
+
+    Ok(world)
+}
+
+fn mtl_material(
+    material: &tobj::Material,
+    dir: &Path,
+    textures: &mut HashMap<PathBuf, Arc<dyn Texture>>,
+) -> Result<Arc<dyn Material>, ObjError> {
+    let color = |c: [f32; 3]| Color::new(c[0] as f64, c[1] as f64, c[2] as f64);
+    let is_black = |c: Color| c.near_zero();
+
+    let emission = material
+        .unknown_param
+        .get("Ke")
+        .and_then(|ke| parse_color(ke))
+        .unwrap_or(Color::ZERO);
+    let diffuse = material.diffuse.map(color);
+    let specular = material.specular.map(color).unwrap_or(Color::ZERO);
+    let illum = material.illumination_model.unwrap_or(2);
+
+    if !is_black(emission) {
+        return Ok(Arc::new(DiffuseLight::from_color(emission)));
+    }
+
+    if material.dissolve.is_some_and(|d| d < 1.0) || matches!(illum, 4 | 6 | 7)
+    {
+        let ior = material.optical_density.unwrap_or(1.5) as f64;
+        return Ok(Arc::new(Dielectric::new(ior)));
+    }
+
+    if !is_black(specular)
+        && (illum == 3 || diffuse.is_some_and(is_black))
+        && material.diffuse_texture.is_none()
+    {
+        // Phong exponent to fuzz, a sharp highlight is a clean mirror
+        let shininess = material.shininess.unwrap_or(0.0).max(0.0) as f64;
+        let fuzz = (2.0 / (shininess + 2.0)).sqrt().min(1.0);
+        return Ok(Arc::new(Metal::new(specular, fuzz)));
+    }
+
+    let albedo: Arc<dyn Texture> = match &material.diffuse_texture {
+        Some(file) => {
+            let path = dir.join(file);
+            match textures.get(&path) {
+                Some(texture) => texture.clone(),
+                None => {
+                    let texture: Arc<dyn Texture> =
+                        Arc::new(ImageTexture::open(&path).map_err(|err| {
+                            ObjError::Texture(path.clone(), err)
+                        })?);
+                    textures.insert(path, texture.clone());
+                    texture
+                }
+            }
+        }
+        None => {
+            Arc::new(SolidColor::new(diffuse.unwrap_or(Color::from_float(0.8))))
+        }
+    };
+
+    Ok(Arc::new(Lambertian::new(albedo)))
+}
+
+fn parse_color(s: &str) -> Option<Color> {
+    let c = s
+        .split_whitespace()
+        .map(|c| c.parse::<f64>().ok())
+        .collect::<Option<Vec<_>>>()?;
+
+    match c[..] {
+        [r, g, b] => Some(Color::new(r, g, b)),
+        [v] => Some(Color::from_float(v)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        hit::Hittable,
+        ray::Ray,
+        testing::{error, fixture},
+    };
+
+    fn gray() -> Arc<dyn Material> {
+        Arc::new(Lambertian::from_solid_color(Color::from_float(0.5)))
+    }
+
+    #[test]
+    fn loads_objects_with_their_materials() {
+        let default_material = gray();
+        let world =
+            load(&fixture("quads.obj"), default_material.clone()).unwrap();
+        assert_eq!(world.len(), 2);
+
+        let down = |x: f64| {
+            let ray =
+                Ray::new(Point3::new(x, 0.5, 1.0), Vec3::from_z(-1.0), 0.0);
+            world.hit(&ray, 0.001, f64::INFINITY).unwrap()
+        };
+
+        let lamp = down(0.25);
+        assert_eq!(
+            lamp.mat.color_emitted(lamp.u, lamp.v, &lamp.p),
+            Color::new(4.0, 3.0, 2.0)
+        );
+
+        // `none` isn't in the MTL file
+        let floor = down(2.25);
+        assert!(Arc::ptr_eq(&floor.mat, &default_material));
+        assert!((floor.u - 0.25).abs() < 1e-6 && (floor.v - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn maps_mtl_materials() {
+        let dir = Path::new("");
+        let mut textures = HashMap::new();
+        let lamp = tobj::Material {
+            diffuse: Some([0.5, 0.5, 0.5]),
+            unknown_param: [(String::from("Ke"), String::from("2 1 0"))]
+                .into_iter()
+                .collect(),
+            ..Default::default()
+        };
+        let lamp = mtl_material(&lamp, dir, &mut textures).unwrap();
+        assert_eq!(
+            lamp.color_emitted(0.0, 0.0, &Point3::ZERO),
+            Color::new(2.0, 1.0, 0.0)
+        );
+
+        let matte = tobj::Material {
+            diffuse: Some([0.2, 0.4, 0.6]),
+            ..Default::default()
+        };
+        let matte = mtl_material(&matte, dir, &mut textures).unwrap();
+        assert_eq!(matte.color_emitted(0.0, 0.0, &Point3::ZERO), Color::ZERO);
+
+        assert_eq!(parse_color("1 0.5 0"), Some(Color::new(1.0, 0.5, 0.0)));
+        assert_eq!(parse_color("2"), Some(Color::from_float(2.0)));
+        assert_eq!(parse_color("1 x 0"), None);
+    }
+
+    #[test]
+    fn reports_missing_files_and_empty_meshes() {
+        let path = fixture("missing.obj");
+        let message = error(load(&path, gray()));
+        assert!(message.starts_with(&*path.to_string_lossy()), "{}", message);
+
+        let path = fixture("no_faces.obj");
+        assert_eq!(
+            error(load(&path, gray())),
+            format!("{}: no triangles", path.display())
+        );
+    }
+}
@@ -0,0 +1,179 @@
+use std::f64::consts::PI;
+
+use rand::Rng;
+
+use crate::{
+    hit::Hittable,
+    vec3::{Point3, Vec3},
+};
+
+// A density over directions that can also be sampled, `value` is per unit
+// solid angle
+pub trait Pdf {
+    fn value(&self, direction: &Vec3) -> f64;
+    fn generate(&self) -> Vec3;
+}
+
+impl<T: Pdf + ?Sized> Pdf for &T {
+    fn value(&self, direction: &Vec3) -> f64 {
+        (**self).value(direction)
+    }
+
+    fn generate(&self) -> Vec3 {
+        (**self).generate()
+    }
+}
+
+impl<T: Pdf + ?Sized> Pdf for Box<T> {
+    fn value(&self, direction: &Vec3) -> f64 {
+        (**self).value(direction)
+    }
+
+    fn generate(&self) -> Vec3 {
+        (**self).generate()
+    }
+}
+
+// Proportional to the cosine with `normal`, zero below the surface
+pub struct CosinePdf {
+    normal: Vec3,
+    tangent: Vec3,
+    bitangent: Vec3,
+}
+
+impl CosinePdf {
+    pub fn new(normal: Vec3) -> Self {
+        let normal = normal.normalized();
+        let (tangent, bitangent) = normal.orthonormal_basis();
+        Self {
+            normal,
+            tangent,
+            bitangent,
+        }
+    }
+}
+
+impl Pdf for CosinePdf {
+    fn value(&self, direction: &Vec3) -> f64 {
+        let cosine = direction.normalized().dot(self.normal);
+        cosine.max(0.0) / PI
+    }
+
+    fn generate(&self) -> Vec3 {
+        let mut rng = rand::thread_rng();
+        let r1: f64 = rng.gen();
+        let r2: f64 = rng.gen();
+
+        let phi = 2.0 * PI * r1;
+        let x = phi.cos() * r2.sqrt();
+        let y = phi.sin() * r2.sqrt();
+        let z = (1.0 - r2).sqrt();
+
+        x * self.tangent + y * self.bitangent + z * self.normal
+    }
+}
+
+// Uniform over all directions
+pub struct SpherePdf;
+
+impl Pdf for SpherePdf {
+    fn value(&self, _direction: &Vec3) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+
+    fn generate(&self) -> Vec3 {
+        Vec3::random_in_unit_sphere().normalized()
+    }
+}
+
+// Directions from `origin` towards a shape, see `Hittable::random`
+pub struct HittablePdf<'a> {
+    hittable: &'a dyn Hittable,
+    origin: Point3,
+}
+
+impl<'a> HittablePdf<'a> {
+    pub fn new(hittable: &'a dyn Hittable, origin: Point3) -> Self {
+        Self { hittable, origin }
+    }
+}
+
+impl Pdf for HittablePdf<'_> {
+    fn value(&self, direction: &Vec3) -> f64 {
+        self.hittable.pdf_value(&self.origin, direction)
+    }
+
+    fn generate(&self) -> Vec3 {
+        self.hittable.random(&self.origin)
+    }
+}
+
+// Samples `a` with probability `weight` and `b` otherwise
+pub struct MixturePdf<A, B> {
+    a: A,
+    b: B,
+    weight: f64,
+}
+
+impl<A: Pdf, B: Pdf> MixturePdf<A, B> {
+    pub fn new(a: A, b: B, weight: f64) -> Self {
+        Self { a, b, weight }
+    }
+}
+
+impl<A: Pdf, B: Pdf> Pdf for MixturePdf<A, B> {
+    fn value(&self, direction: &Vec3) -> f64 {
+        self.weight * self.a.value(direction)
+            + (1.0 - self.weight) * self.b.value(direction)
+    }
+
+    fn generate(&self) -> Vec3 {
+        if rand::thread_rng().gen::<f64>() < self.weight {
+            self.a.generate()
+        } else {
+            self.b.generate()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        material::Lambertian, sphere::Sphere, testing::integral, vec3::Color,
+    };
+
+    #[test]
+    fn densities_integrate_to_one() {
+        let sphere = Sphere::new(
+            Point3::from_z(-1.5),
+            1.0,
+            Arc::new(Lambertian::from_solid_color(Color::ONE)),
+        );
+        let pdfs: [Box<dyn Pdf>; 4] = [
+            Box::new(CosinePdf::new(Vec3::new(1.0, 2.0, 3.0))),
+            Box::new(SpherePdf),
+            Box::new(HittablePdf::new(&sphere, Point3::ZERO)),
+            Box::new(MixturePdf::new(
+                CosinePdf::new(Vec3::from_y(1.0)),
+                HittablePdf::new(&sphere, Point3::ZERO),
+                0.3,
+            )),
+        ];
+
+        for pdf in &pdfs {
+            let integral = integral(pdf.as_ref());
+            assert!((integral - 1.0).abs() < 0.03, "integral {}", integral);
+        }
+    }
+
+    #[test]
+    fn generates_where_the_density_is_positive() {
+        let pdf = CosinePdf::new(Vec3::new(-1.0, 0.5, 0.0));
+        for _ in 0..1000 {
+            assert!(pdf.value(&pdf.generate()) > 0.0);
+        }
+    }
+}
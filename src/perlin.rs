@@ -1,23 +1,26 @@
-use rand::{prelude::SliceRandom, thread_rng, Rng};
+use rand::prelude::SliceRandom;
 
 use crate::vec3::{Point3, Vec3};
 
 #[derive(Clone)]
 pub struct Perlin {
-    ranfloat: Vec<f64>,
     ranvec: Vec<Vec3>,
     perm_x: Vec<usize>,
     perm_y: Vec<usize>,
     perm_z: Vec<usize>,
 }
 
+impl Default for Perlin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Perlin {
     const POINT_COUNT: usize = 256;
 
     pub fn new() -> Self {
         let mut rng = rand::thread_rng();
-        let ranfloat =
-            (0..Self::POINT_COUNT).map(|_| rng.gen::<f64>()).collect();
         let ranvec = (0..Self::POINT_COUNT)
             .map(|_| Vec3::random_in_unit_sphere().normalized())
             .collect();
@@ -29,7 +32,6 @@ impl Perlin {
         };
 
         Perlin {
-            ranfloat,
             ranvec,
             perm_x: gen_perm(),
             perm_y: gen_perm(),
@@ -37,15 +39,6 @@ impl Perlin {
         }
     }
 
-    pub fn _noise(&self, p: &Point3) -> f64 {
-        let i = (p.x()).rem_euclid(Self::POINT_COUNT as f64) as usize;
-        let j = (p.y()).rem_euclid(Self::POINT_COUNT as f64) as usize;
-        let k = (p.z()).rem_euclid(Self::POINT_COUNT as f64) as usize;
-        let index = self.perm_x[i] ^ self.perm_y[j] ^ self.perm_z[k];
-
-        self.ranfloat[index]
-    }
-
     pub fn noise(&self, p: &Point3) -> f64 {
         fn split(x: f64) -> (usize, f64, f64) {
             let r = x.rem_euclid(Perlin::POINT_COUNT as f64);
@@ -0,0 +1,543 @@
+use std::{
+    error::Error,
+    fmt::{self, Display},
+    fs, io,
+    path::{Path, PathBuf},
+    str::{self, SplitAsciiWhitespace},
+    sync::Arc,
+};
+
+use crate::{
+    material::{Lambertian, Material},
+    texture::{SolidColor, VertexColorTexture},
+    triangle::TriangleMesh,
+    vec3::{Color, Point3, Vec3},
+};
+
+#[derive(Debug)]
+pub enum PlyError {
+    Io(PathBuf, io::Error),
+    Invalid(PathBuf, String),
+}
+
+impl Display for PlyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlyError::Io(path, err) => write!(f, "{}: {}", path.display(), err),
+            PlyError::Invalid(path, message) => {
+                write!(f, "{}: {}", path.display(), message)
+            }
+        }
+    }
+}
+
+impl Error for PlyError {}
+
+// Loads the `vertex` and `face` elements of an ASCII or binary PLY file.
+// Without a material the vertex colors are used, or gray when there are none.
+pub fn load(
+    path: &Path,
+    material: Option<Arc<dyn Material>>,
+) -> Result<TriangleMesh, PlyError> {
+    let bytes =
+        fs::read(path).map_err(|err| PlyError::Io(path.to_path_buf(), err))?;
+    let mesh = parse(&bytes)
+        .map_err(|message| PlyError::Invalid(path.to_path_buf(), message))?;
+
+    let material: Arc<dyn Material> = match (material, &mesh.colors) {
+        (Some(material), _) => material,
+        (None, Some(_)) => {
+            Arc::new(Lambertian::new(Arc::new(VertexColorTexture)))
+        }
+        (None, None) => {
+            let gray = Color::from_float(0.8);
+            Arc::new(Lambertian::new(Arc::new(SolidColor::new(gray))))
+        }
+    };
+
+    TriangleMesh::new(
+        mesh.positions,
+        mesh.normals,
+        mesh.uvs,
+        mesh.colors,
+        mesh.indices,
+        material,
+    )
+    .map_err(|err| PlyError::Invalid(path.to_path_buf(), err.to_string()))
+}
+
+struct PlyMesh {
+    positions: Vec<Point3>,
+    normals: Option<Vec<Vec3>>,
+    uvs: Option<Vec<(f64, f64)>>,
+    colors: Option<Vec<Color>>,
+    indices: Vec<[usize; 3]>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Clone, Copy)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn parse(name: &str) -> Result<Self, String> {
+        Ok(match name {
+            "char" | "int8" => Scalar::I8,
+            "uchar" | "uint8" => Scalar::U8,
+            "short" | "int16" => Scalar::I16,
+            "ushort" | "uint16" => Scalar::U16,
+            "int" | "int32" => Scalar::I32,
+            "uint" | "uint32" => Scalar::U32,
+            "float" | "float32" => Scalar::F32,
+            "double" | "float64" => Scalar::F64,
+            _ => return Err(format!("unknown property type `{}`", name)),
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+
+    // integer colors are scaled to 0..1 by the largest value of their type
+    fn color_scale(self) -> f64 {
+        match self {
+            Scalar::I8 => i8::MAX as f64,
+            Scalar::U8 => u8::MAX as f64,
+            Scalar::I16 => i16::MAX as f64,
+            Scalar::U16 => u16::MAX as f64,
+            Scalar::I32 => i32::MAX as f64,
+            Scalar::U32 => u32::MAX as f64,
+            Scalar::F32 | Scalar::F64 => 1.0,
+        }
+    }
+}
+
+enum Property {
+    Scalar(String, Scalar),
+    List(String, Scalar, Scalar),
+}
+
+impl Property {
+    fn name(&self) -> &str {
+        match self {
+            Property::Scalar(name, _) | Property::List(name, _, _) => name,
+        }
+    }
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+impl Element {
+    fn find(&self, names: &[&str]) -> Option<usize> {
+        self.properties
+            .iter()
+            .position(|p| names.contains(&p.name()))
+    }
+}
+
+fn parse(bytes: &[u8]) -> Result<PlyMesh, String> {
+    const END_HEADER: &[u8] = b"end_header";
+
+    let header_end = bytes
+        .windows(END_HEADER.len())
+        .position(|w| w == END_HEADER)
+        .ok_or("missing `end_header`")?;
+    let header = str::from_utf8(&bytes[..header_end])
+        .map_err(|_| "header is not valid text")?;
+
+    // the body starts after the line break following `end_header`
+    let mut body_start = header_end + END_HEADER.len();
+    while body_start < bytes.len() && bytes[body_start] != b'\n' {
+        body_start += 1;
+    }
+    let body = bytes.get(body_start + 1..).unwrap_or_default();
+
+    let (format, elements) = parse_header(header)?;
+    let mut reader = match format {
+        Format::Ascii => Reader::Ascii(
+            str::from_utf8(body)
+                .map_err(|_| "ascii body is not valid text")?
+                .split_ascii_whitespace(),
+        ),
+        _ => Reader::Binary {
+            bytes: body,
+            big_endian: format == Format::BinaryBigEndian,
+        },
+    };
+
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut uvs = Vec::new();
+    let mut colors = Vec::new();
+    let mut indices = Vec::new();
+    let mut has_normals = false;
+    let mut has_uvs = false;
+    let mut has_colors = false;
+
+    for element in &elements {
+        match element.name.as_str() {
+            "vertex" => {
+                let xyz = [
+                    element.find(&["x"]).ok_or("vertex without `x`")?,
+                    element.find(&["y"]).ok_or("vertex without `y`")?,
+                    element.find(&["z"]).ok_or("vertex without `z`")?,
+                ];
+                let normal = [
+                    element.find(&["nx"]),
+                    element.find(&["ny"]),
+                    element.find(&["nz"]),
+                ];
+                let uv = [
+                    element.find(&["u", "s", "texture_u", "texture_s"]),
+                    element.find(&["v", "t", "texture_v", "texture_t"]),
+                ];
+                let rgb = [
+                    element.find(&["red", "r", "diffuse_red"]),
+                    element.find(&["green", "g", "diffuse_green"]),
+                    element.find(&["blue", "b", "diffuse_blue"]),
+                ];
+                let color_scale = match rgb[0].map(|i| &element.properties[i]) {
+                    Some(Property::Scalar(_, scalar)) => scalar.color_scale(),
+                    _ => 1.0,
+                };
+
+                has_normals = normal.iter().all(Option::is_some);
+                has_uvs = uv.iter().all(Option::is_some);
+                has_colors = rgb.iter().all(Option::is_some);
+
+                let mut values = vec![0.0; element.properties.len()];
+                for _ in 0..element.count {
+                    for (value, property) in
+                        values.iter_mut().zip(&element.properties)
+                    {
+                        match property {
+                            Property::Scalar(_, scalar) => {
+                                *value = reader.read(*scalar)?
+                            }
+                            Property::List(..) => reader.skip(property)?,
+                        }
+                    }
+
+                    let get = |i: Option<usize>| i.map_or(0.0, |i| values[i]);
+                    positions.push(Point3::new(
+                        values[xyz[0]],
+                        values[xyz[1]],
+                        values[xyz[2]],
+                    ));
+                    if has_normals {
+                        normals.push(Vec3::new(
+                            get(normal[0]),
+                            get(normal[1]),
+                            get(normal[2]),
+                        ));
+                    }
+                    if has_uvs {
+                        uvs.push((get(uv[0]), get(uv[1])));
+                    }
+                    if has_colors {
+                        colors.push(
+                            Color::new(get(rgb[0]), get(rgb[1]), get(rgb[2]))
+                                / color_scale,
+                        );
+                    }
+                }
+            }
+            "face" => {
+                let list = element
+                    .find(&["vertex_indices", "vertex_index"])
+                    .ok_or("face without `vertex_indices`")?;
+
+                for _ in 0..element.count {
+                    for (i, property) in element.properties.iter().enumerate() {
+                        match property {
+                            Property::List(_, count, item) if i == list => {
+                                let count = reader.read(*count)? as usize;
+                                let polygon = (0..count)
+                                    .map(|_| match reader.read(*item)? {
+                                        i if i < 0.0 => {
+                                            Err(format!("negative index {}", i))
+                                        }
+                                        i => Ok(i as usize),
+                                    })
+                                    .collect::<Result<Vec<_>, String>>()?;
+
+                                // polygons are split into a triangle fan
+                                for k in 1..count.saturating_sub(1) {
+                                    indices.push([
+                                        polygon[0],
+                                        polygon[k],
+                                        polygon[k + 1],
+                                    ]);
+                                }
+                            }
+                            _ => reader.skip(property)?,
+                        }
+                    }
+                }
+            }
+            _ => {
+                for _ in 0..element.count {
+                    for property in &element.properties {
+                        reader.skip(property)?;
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(PlyMesh {
+        positions,
+        normals: has_normals.then_some(normals),
+        uvs: has_uvs.then_some(uvs),
+        colors: has_colors.then_some(colors),
+        indices,
+    })
+}
+
+fn parse_header(header: &str) -> Result<(Format, Vec<Element>), String> {
+    let mut lines = header.lines().map(str::trim);
+    if lines.next() != Some("ply") {
+        return Err(String::from("not a PLY file"));
+    }
+
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+
+    for line in lines {
+        let words: Vec<&str> = line.split_ascii_whitespace().collect();
+        match words[..] {
+            [] | ["comment", ..] | ["obj_info", ..] => {}
+            ["format", name, _version] => {
+                format = Some(match name {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::BinaryLittleEndian,
+                    "binary_big_endian" => Format::BinaryBigEndian,
+                    _ => return Err(format!("unknown format `{}`", name)),
+                })
+            }
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count.parse().map_err(|_| {
+                    format!("invalid element count `{}`", count)
+                })?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count, item, name] => elements
+                .last_mut()
+                .ok_or("property before any element")?
+                .properties
+                .push(Property::List(
+                    name.to_string(),
+                    Scalar::parse(count)?,
+                    Scalar::parse(item)?,
+                )),
+            ["property", scalar, name] => elements
+                .last_mut()
+                .ok_or("property before any element")?
+                .properties
+                .push(Property::Scalar(
+                    name.to_string(),
+                    Scalar::parse(scalar)?,
+                )),
+            _ => return Err(format!("invalid header line `{}`", line)),
+        }
+    }
+
+    Ok((format.ok_or("missing `format`")?, elements))
+}
+
+enum Reader<'a> {
+    Ascii(SplitAsciiWhitespace<'a>),
+    Binary { bytes: &'a [u8], big_endian: bool },
+}
+
+impl Reader<'_> {
+    fn read(&mut self, scalar: Scalar) -> Result<f64, String> {
+        match self {
+            Reader::Ascii(words) => {
+                let word = words.next().ok_or("unexpected end of file")?;
+                word.parse()
+                    .map_err(|_| format!("invalid number `{}`", word))
+            }
+            Reader::Binary { bytes, big_endian } => {
+                let size = scalar.size();
+                if bytes.len() < size {
+                    return Err(String::from("unexpected end of file"));
+                }
+                let (value, rest) = bytes.split_at(size);
+                *bytes = rest;
+
+                let mut buf = [0; 8];
+                buf[..size].copy_from_slice(value);
+                if *big_endian {
+                    buf[..size].reverse();
+                }
+
+                Ok(match scalar {
+                    Scalar::I8 => buf[0] as i8 as f64,
+                    Scalar::U8 => buf[0] as f64,
+                    Scalar::I16 => i16::from_le_bytes([buf[0], buf[1]]) as f64,
+                    Scalar::U16 => u16::from_le_bytes([buf[0], buf[1]]) as f64,
+                    Scalar::I32 => {
+                        i32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]])
+                            as f64
+                    }
+                    Scalar::U32 => {
+                        u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]])
+                            as f64
+                    }
+                    Scalar::F32 => {
+                        f32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]])
+                            as f64
+                    }
+                    Scalar::F64 => f64::from_le_bytes(buf),
+                })
+            }
+        }
+    }
+
+    fn skip(&mut self, property: &Property) -> Result<(), String> {
+        match property {
+            Property::Scalar(_, scalar) => {
+                self.read(*scalar)?;
+            }
+            Property::List(_, count, item) => {
+                for _ in 0..self.read(*count)? as usize {
+                    self.read(*item)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        hit::Hittable,
+        ray::Ray,
+        testing::{error, fixture},
+    };
+
+    #[test]
+    fn reads_ascii_and_binary_files_alike() {
+        for name in ["quad_ascii.ply", "quad_le.ply", "quad_be.ply"] {
+            let mesh = parse(&fs::read(fixture(name)).unwrap()).unwrap();
+            assert_eq!(
+                mesh.positions,
+                [
+                    Point3::ZERO,
+                    Point3::new(1.0, 0.0, 0.0),
+                    Point3::new(1.0, 1.0, 0.0),
+                    Point3::new(0.0, 1.0, 0.0),
+                ],
+                "{}",
+                name
+            );
+            // the quad is split into a fan, the `flags` after it skipped
+            assert_eq!(mesh.indices, [[0, 1, 2], [0, 2, 3]], "{}", name);
+            assert_eq!(
+                mesh.colors.unwrap(),
+                [
+                    Color::new(1.0, 0.0, 0.0),
+                    Color::new(0.0, 1.0, 0.0),
+                    Color::new(0.0, 0.0, 1.0),
+                    Color::ONE,
+                ],
+                "{}",
+                name
+            );
+            assert!(mesh.normals.is_none() && mesh.uvs.is_none(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn loads_vertex_colors_as_the_material() {
+        let mesh = load(&fixture("quad_le.ply"), None).unwrap();
+        let ray = Ray::new(Point3::new(0.1, 0.1, 1.0), Vec3::from_z(-1.0), 0.0);
+        let rec = mesh.hit(&ray, 0.001, f64::INFINITY).unwrap();
+        let color = rec.vertex_color.unwrap();
+        assert!(color.x() > color.y() && color.x() > color.z());
+    }
+
+    #[test]
+    fn reports_malformed_files() {
+        let path = fixture("bad_header.ply");
+        assert_eq!(
+            error(load(&path, None)),
+            format!("{}: invalid header line `element vertex`", path.display())
+        );
+
+        let invalid = |source: &str| error(parse(source.as_bytes()));
+        assert_eq!(invalid("format ascii 1.0\nend_header\n"), "not a PLY file");
+        assert_eq!(invalid("ply\nformat ascii 1.0\n"), "missing `end_header`");
+        assert_eq!(
+            invalid("ply\nformat binary 1.0\nend_header\n"),
+            "unknown format `binary`"
+        );
+        assert_eq!(
+            invalid("ply\nformat ascii 1.0\nproperty float x\nend_header\n"),
+            "property before any element"
+        );
+        assert_eq!(
+            invalid(
+                "ply\nformat ascii 1.0\nelement vertex 1\nproperty half x\n\
+                 end_header\n"
+            ),
+            "unknown property type `half`"
+        );
+
+        let triangle = "ply\nformat ascii 1.0\nelement vertex 3\n\
+            property float x\nproperty float y\nproperty float z\n\
+            element face 1\nproperty list uchar int vertex_indices\n\
+            end_header\n0 0 0 1 0 0 0 1 0\n";
+        assert_eq!(
+            invalid(&format!("{}3 0 1 -1\n", triangle)),
+            "negative index -1"
+        );
+        assert_eq!(
+            invalid(&format!("{}3 0 1\n", triangle)),
+            "unexpected end of file"
+        );
+        assert_eq!(
+            invalid(&format!("{}3 0 1 two\n", triangle)),
+            "invalid number `two`"
+        );
+
+        // the mesh checks its own indices
+        let path = fixture("bad_index.ply");
+        assert_eq!(
+            error(load(&path, None)),
+            format!("{}: index 3 out of range for 3 positions", path.display())
+        );
+
+        // a binary body cut short
+        let mut bytes = fs::read(fixture("quad_be.ply")).unwrap();
+        bytes.truncate(bytes.len() - 2);
+        assert_eq!(error(parse(&bytes)), "unexpected end of file");
+    }
+}
@@ -5,6 +5,8 @@ pub struct Ray {
     direction: Vec3,
     inverse_direction: Vec3,
     time: f64,
+    // the hero wavelength in nm when rendering spectrally
+    wavelength: Option<f64>,
 }
 
 impl Default for Ray {
@@ -14,6 +16,7 @@ impl Default for Ray {
             direction: Vec3::ZERO,
             inverse_direction: Vec3::ZERO,
             time: 0.0,
+            wavelength: None,
         }
     }
 }
@@ -25,9 +28,14 @@ impl Ray {
             direction,
             inverse_direction: 1.0 / direction,
             time,
+            wavelength: None,
         }
     }
 
+    pub fn with_wavelength(self, wavelength: Option<f64>) -> Ray {
+        Ray { wavelength, ..self }
+    }
+
     pub fn origin(&self) -> Point3 {
         self.origin
     }
@@ -44,6 +52,10 @@ impl Ray {
         self.time
     }
 
+    pub fn wavelength(&self) -> Option<f64> {
+        self.wavelength
+    }
+
     pub fn at(&self, t: f64) -> Point3 {
         self.origin + t * self.direction
     }
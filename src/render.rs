@@ -0,0 +1,655 @@
+use std::sync::Arc;
+
+use rand::Rng;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+use crate::{
+    bvh::{self, TraversalStats},
+    camera::Camera,
+    environment::Environment,
+    framebuffer::Framebuffer,
+    hit::{HitRecord, Hittable},
+    material::{Interior, Material, ScatterRecord},
+    pdf::{HittablePdf, Pdf},
+    ray::Ray,
+    scenes::Scene,
+    spectrum::Wavelengths,
+    vec3::{Color, Vec3},
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Integrator {
+    // samples the lights and the materials, combined with multiple
+    // importance sampling
+    Mis,
+    // only follows the materials, slow to find small lights but simple
+    // enough to check the other one against
+    Bsdf,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct RenderSettings {
+    // shutter interval, moving objects are blurred over it
+    pub time0: f64,
+    pub time1: f64,
+    // print the remaining scanlines to stderr
+    pub progress: bool,
+    pub integrator: Integrator,
+    // carry wavelengths along the paths instead of red, green and blue, for
+    // dispersive materials
+    pub spectral: bool,
+    // count the BVH nodes and primitives the render visits, which costs a
+    // little time
+    pub bvh_stats: bool,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            time0: 0.0,
+            time1: 1.0,
+            progress: false,
+            integrator: Integrator::Mis,
+            spectral: false,
+            bvh_stats: false,
+        }
+    }
+}
+
+pub fn render(scene: &Scene, settings: &RenderSettings) -> Framebuffer {
+    let (framebuffer, _) = render_with_stats(scene, settings);
+    framebuffer
+}
+
+// Also returns the BVH work of the render when `settings.bvh_stats` is set
+pub fn render_with_stats(
+    scene: &Scene,
+    settings: &RenderSettings,
+) -> (Framebuffer, Option<TraversalStats>) {
+    let camera = Camera::new(scene, settings.time0, settings.time1);
+
+    let mut framebuffer =
+        Framebuffer::new(scene.image_width as u32, scene.image_height as u32);
+    let mut stats = TraversalStats::default();
+
+    for j in (0..scene.image_height).rev() {
+        if settings.progress {
+            eprintln!("\rScanlines remaining: {:?}", j + 1);
+        }
+
+        let (scanline, row_stats): (Vec<Color>, Vec<TraversalStats>) = (0
+            ..scene.image_width)
+            .into_par_iter()
+            .map(|i| {
+                let pixel = || {
+                    let mut pixel_color = Color::new(0.0, 0.0, 0.0);
+                    for _ in 0..scene.samples_per_pixel {
+                        let mut rng = rand::thread_rng();
+                        let random_u: f64 = rng.gen();
+                        let random_v: f64 = rng.gen();
+
+                        let u = ((i as f64) + random_u)
+                            / ((scene.image_width - 1) as f64);
+                        let v = ((j as f64) + random_v)
+                            / ((scene.image_height - 1) as f64);
+
+                        let r = camera.get_ray(u, v);
+                        pixel_color += path_color(
+                            r,
+                            scene,
+                            settings.integrator,
+                            settings.spectral,
+                        );
+                    }
+                    pixel_color / scene.samples_per_pixel as f64
+                };
+
+                if settings.bvh_stats {
+                    bvh::count_stats(pixel)
+                } else {
+                    (pixel(), TraversalStats::default())
+                }
+            })
+            .unzip();
+        for pixel_stats in row_stats {
+            stats += pixel_stats;
+        }
+
+        let row = (scene.image_height - 1 - j) as u32;
+        framebuffer.set_row(row, &scanline);
+    }
+
+    (framebuffer, settings.bvh_stats.then_some(stats))
+}
+
+// Bounces before Russian roulette may end a path
+const ROULETTE_DEPTH: u64 = 3;
+
+fn path_color(
+    ray: Ray,
+    scene: &Scene,
+    integrator: Integrator,
+    spectral: bool,
+) -> Color {
+    let mis = integrator == Integrator::Mis;
+    let sample_lights = mis && !scene.lights.is_empty();
+    let wavelengths = spectral.then(Wavelengths::random);
+    let mut ray = ray.with_wavelength(wavelengths.map(|w| w.hero()));
+
+    let mut rng = rand::thread_rng();
+    let mut radiance = Color::ZERO;
+    let mut throughput = Color::ONE;
+    // density the material sampled `ray` with, None when the lights could
+    // not have sampled it: after the camera, a specular bounce or without
+    // light sampling
+    let mut bsdf_pdf: Option<f64> = None;
+    // where the material scattered `ray` from, it's respawned past the
+    // surfaces `Media::hit` skips
+    let mut scatter_origin = ray.origin();
+    // whether the lights without a surface were sampled where `ray` starts,
+    // otherwise it can see them
+    let mut punctual_sampled = false;
+    let mut media = Media::default();
+    // whether only the hero wavelength is left
+    let mut dispersed = false;
+
+    for depth in 0..scene.max_depth {
+        let Some(rec) =
+            media.hit(scene, &mut ray, &mut throughput, wavelengths)
+        else {
+            radiance += throughput
+                * miss(
+                    &ray,
+                    scene,
+                    wavelengths,
+                    depth == 0,
+                    punctual_sampled,
+                    bsdf_pdf,
+                );
+            break;
+        };
+
+        let emitted = rec.mat.color_emitted(rec.u, rec.v, &rec.p);
+        if emitted != Color::ZERO {
+            let weight = match bsdf_pdf {
+                Some(bsdf_pdf) => {
+                    let light_pdf = scene
+                        .lights
+                        .pdf_value(&scatter_origin, &ray.direction());
+                    power_heuristic(bsdf_pdf, light_pdf)
+                }
+                None => 1.0,
+            };
+            radiance +=
+                weight * throughput * spectral_color(wavelengths, emitted);
+        }
+
+        let Some(scatter) = rec.mat.scatter(&ray, &rec) else {
+            break;
+        };
+        // what's scattered past a dispersive or iridescent surface only suits
+        // the hero, the other wavelengths would have gone elsewhere
+        if wavelengths.is_some()
+            && !dispersed
+            && rec.mat.depends_on_wavelength()
+        {
+            throughput = Color::new(3.0 * throughput.x(), 0.0, 0.0);
+            dispersed = true;
+        }
+
+        let pdf = match scatter {
+            ScatterRecord::Specular {
+                attenuation,
+                ray: scattered,
+            } => {
+                throughput =
+                    throughput * spectral_color(wavelengths, attenuation);
+                media.cross(&rec, &scattered.direction());
+                ray = scattered.with_wavelength(ray.wavelength());
+                bsdf_pdf = None;
+                punctual_sampled = false;
+                continue;
+            }
+            ScatterRecord::Sampled { pdf } => pdf,
+        };
+
+        // the shadow ray is one more segment, like the bounce would be
+        if depth + 1 < scene.max_depth {
+            if sample_lights {
+                radiance += throughput
+                    * direct_light(
+                        &ray,
+                        &rec,
+                        scene,
+                        &media,
+                        wavelengths,
+                        &pdf,
+                    );
+            }
+            if let Some(environment) =
+                scene.environment.as_deref().filter(|_| mis)
+            {
+                radiance += throughput
+                    * environment_light(
+                        &ray,
+                        &rec,
+                        scene,
+                        &media,
+                        environment,
+                        wavelengths,
+                        &pdf,
+                    );
+            }
+            radiance += throughput
+                * punctual_light(&ray, &rec, scene, &media, wavelengths);
+        }
+
+        let scattered = Ray::new(rec.p, pdf.generate(), ray.time())
+            .with_wavelength(ray.wavelength());
+        let pdf_value = pdf.value(&scattered.direction());
+        if pdf_value <= 0.0 {
+            break;
+        }
+        throughput = throughput
+            * spectral_color(
+                wavelengths,
+                rec.mat.eval(&ray, &rec, &scattered.direction()),
+            )
+            / pdf_value;
+        media.cross(&rec, &scattered.direction());
+        ray = scattered;
+        scatter_origin = rec.p;
+        bsdf_pdf = mis.then_some(pdf_value);
+        punctual_sampled = true;
+
+        if depth + 1 >= ROULETTE_DEPTH {
+            let survival = throughput
+                .x()
+                .max(throughput.y())
+                .max(throughput.z())
+                .min(0.95);
+            if rng.gen::<f64>() >= survival {
+                break;
+            }
+            throughput /= survival;
+        }
+    }
+
+    match wavelengths {
+        Some(wavelengths) => wavelengths.to_rgb(radiance),
+        None => radiance,
+    }
+}
+
+// A color of the scene in the channels a path carries
+fn spectral_color(wavelengths: Option<Wavelengths>, rgb: Color) -> Color {
+    match wavelengths {
+        Some(wavelengths) => wavelengths.upsample(rgb),
+        None => rgb,
+    }
+}
+
+// The dielectrics a path is inside of, in the order it entered them
+#[derive(Clone, Default)]
+struct Media(Vec<(Arc<dyn Material>, Interior)>);
+
+impl Media {
+    // The next surface `ray` meets, skipping the ones hidden inside a higher
+    // priority medium, with the light absorbed on the way there
+    fn hit(
+        &mut self,
+        scene: &Scene,
+        ray: &mut Ray,
+        throughput: &mut Color,
+        wavelengths: Option<Wavelengths>,
+    ) -> Option<HitRecord> {
+        loop {
+            bvh::record_ray();
+            let mut rec = scene.world.hit(ray, 0.001, f64::INFINITY)?;
+            *throughput = *throughput
+                * self.transmittance(
+                    rec.t * ray.direction().length(),
+                    wavelengths,
+                );
+
+            let Some(interior) = rec.mat.interior() else {
+                return Some(rec);
+            };
+            let priority = self.current(None).map_or(0, |c| c.priority);
+            if interior.priority < priority {
+                self.cross(&rec, &ray.direction());
+                *ray = Ray::new(rec.p, ray.direction(), ray.time())
+                    .with_wavelength(ray.wavelength());
+                continue;
+            }
+
+            // entering, what's around is what the ray is in, leaving, it's
+            // what's left once out
+            let outside = if rec.front_face {
+                self.current(None)
+            } else {
+                self.current(Some(&rec.mat))
+            };
+            rec.outside_index_of_refraction = outside
+                .map_or(1.0, |c| c.index_of_refraction.at(ray.wavelength()));
+            return Some(rec);
+        }
+    }
+
+    // What a shadow ray leaving `rec` along `direction` meets first before
+    // `t_max`, passing through the surfaces `hit` would skip, and the light
+    // left after the media on the way. A dielectric the ray would refract
+    // through stops it like any other surface: the light it lets through is
+    // only found by the paths crossing it
+    fn shadow(
+        &self,
+        scene: &Scene,
+        rec: &HitRecord,
+        direction: Vec3,
+        time: f64,
+        mut t_max: f64,
+        wavelengths: Option<Wavelengths>,
+    ) -> (Option<HitRecord>, Color) {
+        let mut media = self.clone();
+        media.cross(rec, &direction);
+        let mut ray = Ray::new(rec.p, direction, time);
+        let mut transmittance = Color::ONE;
+        loop {
+            bvh::record_ray();
+            let Some(hit) = scene.world.hit(&ray, 0.001, t_max) else {
+                if t_max.is_finite() {
+                    transmittance = transmittance
+                        * media.transmittance(
+                            t_max * direction.length(),
+                            wavelengths,
+                        );
+                }
+                return (None, transmittance);
+            };
+            transmittance = transmittance
+                * media.transmittance(hit.t * direction.length(), wavelengths);
+
+            let priority = media.current(None).map_or(0, |c| c.priority);
+            match hit.mat.interior() {
+                Some(interior) if interior.priority < priority => {
+                    media.cross(&hit, &direction);
+                    ray = Ray::new(hit.p, direction, time);
+                    t_max -= hit.t;
+                }
+                _ => return (Some(hit), transmittance),
+            }
+        }
+    }
+
+    // The light left after `distance` through the current medium
+    fn transmittance(
+        &self,
+        distance: f64,
+        wavelengths: Option<Wavelengths>,
+    ) -> Color {
+        let Some(current) = self.current(None) else {
+            return Color::ONE;
+        };
+        let absorption = spectral_color(wavelengths, current.absorption);
+        Color::new(
+            (-absorption.x() * distance).exp(),
+            (-absorption.y() * distance).exp(),
+            (-absorption.z() * distance).exp(),
+        )
+    }
+
+    // Enters or leaves the object at `rec` when `direction` goes through
+    fn cross(&mut self, rec: &HitRecord, direction: &Vec3) {
+        let Some(interior) = rec.mat.interior() else {
+            return;
+        };
+        if direction.dot(rec.normal) >= 0.0 {
+            return;
+        }
+
+        if rec.front_face {
+            self.0.push((rec.mat.clone(), interior));
+        } else if let Some(i) = self.position(&rec.mat) {
+            self.0.remove(i);
+        }
+    }
+
+    // The medium light travels through, the highest priority one and the
+    // latest entered of those, without `leaving`
+    fn current(
+        &self,
+        leaving: Option<&Arc<dyn Material>>,
+    ) -> Option<&Interior> {
+        let leaving = leaving.and_then(|material| self.position(material));
+        self.0
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| Some(*i) != leaving)
+            .max_by_key(|(_, (_, interior))| interior.priority)
+            .map(|(_, (_, interior))| interior)
+    }
+
+    fn position(&self, material: &Arc<dyn Material>) -> Option<usize> {
+        self.0
+            .iter()
+            .rposition(|(entered, _)| Arc::ptr_eq(entered, material))
+    }
+}
+
+// What a ray leaving the scene sees. The environment can be hidden from the
+// camera while still lighting the scene, the flat background shows instead
+fn miss(
+    ray: &Ray,
+    scene: &Scene,
+    wavelengths: Option<Wavelengths>,
+    camera_ray: bool,
+    punctual_sampled: bool,
+    bsdf_pdf: Option<f64>,
+) -> Color {
+    if camera_ray && !scene.environment_visible {
+        return spectral_color(wavelengths, scene.background);
+    }
+
+    let direction = ray.direction().normalized();
+    let mut radiance = match &scene.environment {
+        Some(environment) => {
+            let weight = bsdf_pdf.map_or(1.0, |bsdf_pdf| {
+                power_heuristic(bsdf_pdf, environment.pdf_value(&direction))
+            });
+            weight
+                * spectral_color(wavelengths, environment.radiance(&direction))
+        }
+        None => spectral_color(wavelengths, scene.background),
+    };
+    if !punctual_sampled {
+        for light in &scene.punctual_lights {
+            radiance += spectral_color(wavelengths, light.radiance(&direction));
+        }
+    }
+    radiance
+}
+
+// Light from a direction sampled on the environment, weighted against the
+// chance of the material sampling it
+fn environment_light(
+    ray: &Ray,
+    rec: &HitRecord,
+    scene: &Scene,
+    media: &Media,
+    environment: &dyn Environment,
+    wavelengths: Option<Wavelengths>,
+    material_pdf: &dyn Pdf,
+) -> Color {
+    let direction = environment.random();
+    let environment_pdf = environment.pdf_value(&direction);
+    if environment_pdf <= 0.0 {
+        return Color::ZERO;
+    }
+
+    let bsdf = rec.mat.eval(ray, rec, &direction);
+    if bsdf == Color::ZERO {
+        return Color::ZERO;
+    }
+
+    let (blocker, transmittance) = media.shadow(
+        scene,
+        rec,
+        direction,
+        ray.time(),
+        f64::INFINITY,
+        wavelengths,
+    );
+    if blocker.is_some() {
+        return Color::ZERO;
+    }
+
+    let weight =
+        power_heuristic(environment_pdf, material_pdf.value(&direction));
+    weight
+        * transmittance
+        * spectral_color(wavelengths, bsdf)
+        * spectral_color(wavelengths, environment.radiance(&direction))
+        / environment_pdf
+}
+
+// Light from a point sampled on the lights, weighted against the chance of
+// the material sampling the same direction. Whatever the shadow ray hits first
+// is either an emitter or what shadows it
+fn direct_light(
+    ray: &Ray,
+    rec: &HitRecord,
+    scene: &Scene,
+    media: &Media,
+    wavelengths: Option<Wavelengths>,
+    material_pdf: &dyn Pdf,
+) -> Color {
+    let light_pdf = HittablePdf::new(&scene.lights, rec.p);
+
+    let direction = light_pdf.generate();
+    let light_pdf = light_pdf.value(&direction);
+    if light_pdf <= 0.0 {
+        return Color::ZERO;
+    }
+
+    let bsdf = rec.mat.eval(ray, rec, &direction);
+    if bsdf == Color::ZERO {
+        return Color::ZERO;
+    }
+
+    match media.shadow(
+        scene,
+        rec,
+        direction,
+        ray.time(),
+        f64::INFINITY,
+        wavelengths,
+    ) {
+        (Some(light), transmittance) => {
+            let emitted = light.mat.color_emitted(light.u, light.v, &light.p);
+            let weight =
+                power_heuristic(light_pdf, material_pdf.value(&direction));
+            weight
+                * transmittance
+                * spectral_color(wavelengths, bsdf)
+                * spectral_color(wavelengths, emitted)
+                / light_pdf
+        }
+        (None, _) => Color::ZERO,
+    }
+}
+
+// Light from the lights without a surface, each one sampled once
+fn punctual_light(
+    ray: &Ray,
+    rec: &HitRecord,
+    scene: &Scene,
+    media: &Media,
+    wavelengths: Option<Wavelengths>,
+) -> Color {
+    let mut radiance = Color::ZERO;
+
+    for light in &scene.punctual_lights {
+        let Some(sample) = light.sample(&rec.p) else {
+            continue;
+        };
+        let bsdf = rec.mat.eval(ray, rec, &sample.direction);
+        if bsdf == Color::ZERO {
+            continue;
+        }
+
+        let (blocker, transmittance) = media.shadow(
+            scene,
+            rec,
+            sample.direction,
+            ray.time(),
+            sample.distance - 0.001,
+            wavelengths,
+        );
+        if blocker.is_none() {
+            radiance += transmittance
+                * spectral_color(wavelengths, bsdf)
+                * spectral_color(wavelengths, sample.radiance);
+        }
+    }
+
+    radiance
+}
+
+// Veach's power heuristic with an exponent of 2, for one sample of each
+// strategy
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b > 0.0 {
+        a / (a + b)
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        material::{Dielectric, Lambertian},
+        sphere::Sphere,
+    };
+
+    #[test]
+    fn shadow_rays_pass_through_skipped_surfaces_and_absorb() {
+        let glass: Arc<dyn Material> = Arc::new(
+            Dielectric::new(1.5)
+                .with_absorption(Color::from_float(0.5))
+                .with_priority(1),
+        );
+        let media = Media(vec![(glass.clone(), glass.interior().unwrap())]);
+        let mut rec = HitRecord::default(Arc::new(
+            Lambertian::from_solid_color(Color::ONE),
+        ));
+        rec.normal = Vec3::from_y(1.0);
+        rec.front_face = true;
+        let up = Vec3::from_y(1.0);
+
+        // a liquid inside the glass is ignored, only the glass absorbs
+        let mut scene = Scene::default();
+        scene.world.push(Box::new(Sphere::new(
+            Vec3::from_y(1.0),
+            0.3,
+            Arc::new(Dielectric::new(1.33).with_absorption(Color::ONE)),
+        )));
+        let (blocker, transmittance) =
+            media.shadow(&scene, &rec, up, 0.0, 2.0, None);
+        assert!(blocker.is_none());
+        assert!(
+            (transmittance - Color::from_float((-1.0f64).exp())).near_zero()
+        );
+
+        // a surface the ray would refract through blocks it
+        scene.world.push(Box::new(Sphere::new(
+            Vec3::from_y(1.0),
+            0.5,
+            Arc::new(Dielectric::new(1.5).with_priority(2)),
+        )));
+        let (blocker, _) = media.shadow(&scene, &rec, up, 0.0, 2.0, None);
+        assert!(blocker.is_some());
+    }
+}
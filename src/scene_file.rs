@@ -0,0 +1,1251 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt::{self, Display},
+    fs, io,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use serde::Deserialize;
+use toml::Spanned;
+
+use crate::{
+    aarect::{XYRect, XZRect, YZRect},
+    box3::Box3,
+    bvh::{Bvh, SplitMethod},
+    constant_medium::ConstantMedium,
+    environment::{Environment, EnvironmentMap},
+    hit::{Hittable, RotateY, Translate, World},
+    light::{DirectionalLight, Light, PointLight, SpotLight},
+    material::{
+        Coated, Dielectric, DiffuseLight, Ior, Isotropic, Lambertian, Material,
+        Metal, OrenNayar, Principled, RoughConductor, RoughDielectric,
+        ThinFilm,
+    },
+    moving_sphere::MovingSphere,
+    obj, ply,
+    scenes::Scene,
+    sky::PreethamSky,
+    sphere::Sphere,
+    stl,
+    texture::{
+        CheckerTexture, ImageTexture, NoiseTexture, SolidColor, Texture,
+    },
+    triangle::{MeshError, Triangle, TriangleMesh},
+    vec3::Vec3,
+};
+
+#[derive(Debug)]
+pub enum SceneFileError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+    Invalid {
+        path: PathBuf,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+}
+
+impl Display for SceneFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneFileError::Io(path, err) => {
+                write!(f, "{}: {}", path.display(), err)
+            }
+            SceneFileError::Parse(path, err) => {
+                write!(f, "{}: {}", path.display(), err)
+            }
+            SceneFileError::Invalid {
+                path,
+                line,
+                column,
+                message,
+            } => {
+                write!(f, "{}:{}:{}: {}", path.display(), line, column, message)
+            }
+        }
+    }
+}
+
+impl Error for SceneFileError {}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneDesc {
+    #[serde(default)]
+    camera: CameraDesc,
+    #[serde(default)]
+    render: RenderDesc,
+    #[serde(default)]
+    textures: HashMap<String, Spanned<TextureDesc>>,
+    #[serde(default)]
+    materials: HashMap<String, MaterialDesc>,
+    #[serde(default)]
+    objects: Vec<ObjectDesc>,
+    #[serde(default)]
+    lights: Vec<LightDesc>,
+    environment: Option<EnvironmentDesc>,
+    sky: Option<Spanned<SkyDesc>>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct CameraDesc {
+    lookfrom: Option<[f64; 3]>,
+    lookat: Option<[f64; 3]>,
+    vup: Option<[f64; 3]>,
+    vfov: Option<f64>,
+    aperture: Option<f64>,
+    dist_to_focus: Option<f64>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct RenderDesc {
+    aspect_ratio: Option<Spanned<f64>>,
+    image_width: Option<Spanned<u64>>,
+    samples_per_pixel: Option<Spanned<u64>>,
+    max_depth: Option<Spanned<u64>>,
+    background: Option<[f64; 3]>,
+}
+
+// A texture is either an inline color, a gray level, or the name of an entry
+// in `textures`
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TextureRef {
+    Color([f64; 3]),
+    Value(f64),
+    Name(String),
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+enum TextureDesc {
+    Solid {
+        color: [f64; 3],
+    },
+    Checker {
+        odd: Spanned<TextureRef>,
+        even: Spanned<TextureRef>,
+    },
+    Noise {
+        scale: f64,
+    },
+    Image {
+        file: PathBuf,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+enum MaterialDesc {
+    Lambertian {
+        albedo: Spanned<TextureRef>,
+    },
+    // `sigma` is the roughness in degrees
+    OrenNayar {
+        albedo: Spanned<TextureRef>,
+        sigma: f64,
+    },
+    Metal {
+        albedo: [f64; 3],
+        fuzz: f64,
+        film: Option<FilmDesc>,
+    },
+    // GGX rough with a `roughness`, perfectly smooth without
+    Conductor {
+        ior: ConductorIor,
+        roughness: Option<Spanned<TextureRef>>,
+        film: Option<FilmDesc>,
+    },
+    // `absorption` per unit of distance inside, `priority` picks which of
+    // two overlapping dielectrics fills the overlap
+    Dielectric {
+        index_of_refraction: IorDesc,
+        roughness: Option<Spanned<TextureRef>>,
+        #[serde(default)]
+        absorption: [f64; 3],
+        #[serde(default)]
+        priority: u32,
+        // only on smooth ones
+        film: Option<FilmDesc>,
+    },
+    // every parameter is optional, see `Principled`
+    Principled(Box<PrincipledDesc>),
+    // a varnish over the inline `base` material, smooth without a
+    // `roughness`, `tint` is the color after crossing `thickness` 1 head on
+    Coated {
+        base: Box<MaterialDesc>,
+        #[serde(default = "varnish")]
+        index_of_refraction: f64,
+        roughness: Option<Spanned<TextureRef>>,
+        #[serde(default = "white")]
+        tint: [f64; 3],
+        #[serde(default = "one")]
+        thickness: f64,
+    },
+    DiffuseLight {
+        emit: Spanned<TextureRef>,
+    },
+    Isotropic {
+        albedo: Spanned<TextureRef>,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PrincipledDesc {
+    base_color: Option<Spanned<TextureRef>>,
+    metallic: Option<Spanned<TextureRef>>,
+    roughness: Option<Spanned<TextureRef>>,
+    specular: Option<Spanned<TextureRef>>,
+    sheen: Option<Spanned<TextureRef>>,
+    clearcoat: Option<Spanned<TextureRef>>,
+    clearcoat_roughness: Option<Spanned<TextureRef>>,
+    transmission: Option<Spanned<TextureRef>>,
+    subsurface: Option<Spanned<TextureRef>>,
+    emission: Option<Spanned<TextureRef>>,
+}
+
+// `thickness` in nm
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FilmDesc {
+    thickness: Spanned<TextureRef>,
+    index_of_refraction: f64,
+}
+
+// A number, or a formula of the wavelength for dispersion
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum IorDesc {
+    Constant(f64),
+    Dispersive(DispersionDesc),
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+enum DispersionDesc {
+    Cauchy { a: f64, b: f64 },
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+impl From<&IorDesc> for Ior {
+    fn from(desc: &IorDesc) -> Self {
+        match *desc {
+            IorDesc::Constant(n) => Ior::Constant(n),
+            IorDesc::Dispersive(DispersionDesc::Cauchy { a, b }) => {
+                Ior::Cauchy { a, b }
+            }
+            IorDesc::Dispersive(DispersionDesc::Sellmeier { b, c }) => {
+                Ior::Sellmeier { b, c }
+            }
+        }
+    }
+}
+
+// A known metal, or the complex index of refraction per channel
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ConductorIor {
+    Metal(MetalName),
+    Measured { eta: [f64; 3], k: [f64; 3] },
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum MetalName {
+    Gold,
+    Copper,
+    Aluminium,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+enum ObjectDesc {
+    Sphere {
+        center: [f64; 3],
+        radius: f64,
+        material: Spanned<String>,
+    },
+    MovingSphere {
+        center0: [f64; 3],
+        center1: [f64; 3],
+        #[serde(default)]
+        time0: f64,
+        #[serde(default = "one")]
+        time1: f64,
+        radius: f64,
+        material: Spanned<String>,
+    },
+    XyRect {
+        x0: f64,
+        x1: f64,
+        y0: f64,
+        y1: f64,
+        k: f64,
+        material: Spanned<String>,
+    },
+    XzRect {
+        x0: f64,
+        x1: f64,
+        z0: f64,
+        z1: f64,
+        k: f64,
+        material: Spanned<String>,
+    },
+    YzRect {
+        y0: f64,
+        y1: f64,
+        z0: f64,
+        z1: f64,
+        k: f64,
+        material: Spanned<String>,
+    },
+    Box {
+        min: [f64; 3],
+        max: [f64; 3],
+        material: Spanned<String>,
+    },
+    Triangle {
+        v0: [f64; 3],
+        v1: [f64; 3],
+        v2: [f64; 3],
+        material: Spanned<String>,
+    },
+    Mesh {
+        positions: Vec<[f64; 3]>,
+        normals: Option<Spanned<Vec<[f64; 3]>>>,
+        uvs: Option<Spanned<Vec<[f64; 2]>>>,
+        indices: Spanned<Vec<[usize; 3]>>,
+        material: Spanned<String>,
+    },
+    Obj {
+        file: Spanned<PathBuf>,
+        material: Option<Spanned<String>>,
+    },
+    Ply {
+        file: Spanned<PathBuf>,
+        material: Option<Spanned<String>>,
+    },
+    Stl {
+        file: Spanned<PathBuf>,
+        material: Option<Spanned<String>>,
+    },
+    ConstantMedium {
+        boundary: Box<ObjectDesc>,
+        density: f64,
+        albedo: Spanned<TextureRef>,
+    },
+    Bvh {
+        objects: Spanned<Vec<ObjectDesc>>,
+    },
+    Translate {
+        offset: [f64; 3],
+        object: Box<ObjectDesc>,
+    },
+    RotateY {
+        angle: f64,
+        object: Box<ObjectDesc>,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+enum LightDesc {
+    Point {
+        position: [f64; 3],
+        intensity: [f64; 3],
+    },
+    Spot {
+        position: [f64; 3],
+        direction: Spanned<[f64; 3]>,
+        intensity: [f64; 3],
+        inner_angle: f64,
+        outer_angle: f64,
+    },
+    Directional {
+        direction: Spanned<[f64; 3]>,
+        irradiance: [f64; 3],
+        #[serde(default)]
+        angular_diameter: f64,
+    },
+}
+
+// An equirectangular HDR or EXR image lighting the scene from all around
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EnvironmentDesc {
+    file: Spanned<PathBuf>,
+    // degrees around +y
+    #[serde(default)]
+    rotation: f64,
+    #[serde(default = "one")]
+    intensity: f64,
+    // whether camera rays see it, or the background instead
+    #[serde(default = "yes")]
+    visible: bool,
+}
+
+// An analytic daylight sky, and the sun as a light
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SkyDesc {
+    // towards the sun
+    sun_direction: Spanned<[f64; 3]>,
+    // haziness, from 2 for a clear sky to 10
+    #[serde(default = "clear")]
+    turbidity: f64,
+    #[serde(default = "gray")]
+    ground_albedo: [f64; 3],
+    #[serde(default = "one")]
+    intensity: f64,
+    #[serde(default = "yes")]
+    visible: bool,
+}
+
+fn clear() -> f64 {
+    3.0
+}
+
+fn gray() -> [f64; 3] {
+    [0.3, 0.3, 0.3]
+}
+
+fn varnish() -> f64 {
+    1.5
+}
+
+fn white() -> [f64; 3] {
+    [1.0, 1.0, 1.0]
+}
+
+fn yes() -> bool {
+    true
+}
+
+fn one() -> f64 {
+    1.0
+}
+
+pub fn load(path: &Path) -> Result<Scene, SceneFileError> {
+    load_with_split_method(path, SplitMethod::Sah)
+}
+
+// Builds the `bvh` objects and OBJ files with `split_method`, meshes are
+// always split by SAH
+pub fn load_with_split_method(
+    path: &Path,
+    split_method: SplitMethod,
+) -> Result<Scene, SceneFileError> {
+    let source = fs::read_to_string(path)
+        .map_err(|err| SceneFileError::Io(path.to_path_buf(), err))?;
+    let desc: SceneDesc = toml::from_str(&source)
+        .map_err(|err| SceneFileError::Parse(path.to_path_buf(), err))?;
+
+    let mut builder = Builder {
+        path,
+        source: &source,
+        texture_descs: &desc.textures,
+        textures: HashMap::new(),
+        materials: HashMap::new(),
+        split_method,
+    };
+
+    let mut texture_names: Vec<&String> = desc.textures.keys().collect();
+    texture_names.sort();
+    for name in texture_names {
+        builder.named_texture(name, &mut Vec::new())?;
+    }
+
+    let mut material_names: Vec<&String> = desc.materials.keys().collect();
+    material_names.sort();
+    for name in material_names {
+        let material = builder.material(&desc.materials[name])?;
+        builder.materials.insert(name.clone(), material);
+    }
+
+    let world = desc
+        .objects
+        .iter()
+        .map(|object| builder.object(object))
+        .collect::<Result<World, _>>()?;
+
+    // emissive spheres and rects are also sampled as lights
+    let lights = desc
+        .objects
+        .iter()
+        .filter(|object| is_light(object, &desc.materials))
+        .map(|object| builder.object(object))
+        .collect::<Result<World, _>>()?;
+
+    let mut punctual_lights = desc
+        .lights
+        .iter()
+        .map(|light| builder.light(light))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let (environment, environment_visible) =
+        match (&desc.environment, &desc.sky) {
+            (Some(_), Some(sky)) => {
+                return Err(builder.error(
+                    sky.span(),
+                    String::from(
+                        "a scene can't have both an environment and a sky",
+                    ),
+                ))
+            }
+            (Some(environment), None) => {
+                (Some(builder.environment(environment)?), environment.visible)
+            }
+            (None, Some(sky)) => {
+                let environment = builder.sky(sky.get_ref())?;
+                punctual_lights.push(Box::new(environment.sun()));
+                (Some(Box::new(environment) as _), sky.get_ref().visible)
+            }
+            (None, None) => (None, true),
+        };
+
+    let mut scene = Scene {
+        world,
+        lights,
+        punctual_lights,
+        environment,
+        environment_visible,
+        ..Scene::default()
+    };
+
+    let camera = desc.camera;
+    scene.lookfrom = camera.lookfrom.map_or(scene.lookfrom, Vec3::from);
+    scene.lookat = camera.lookat.map_or(scene.lookat, Vec3::from);
+    scene.vup = camera.vup.map_or(scene.vup, Vec3::from);
+    scene.vfov = camera.vfov.unwrap_or(scene.vfov);
+    scene.aperture = camera.aperture.unwrap_or(scene.aperture);
+    scene.dist_to_focus = camera.dist_to_focus.unwrap_or(scene.dist_to_focus);
+
+    // the same bounds as the command line flags
+    let at_least =
+        |value: &Option<Spanned<u64>>, name: &str, min: u64| match value {
+            Some(value) if *value.get_ref() < min => Err(builder.error(
+                value.span(),
+                format!("{} must be at least {}", name, min),
+            )),
+            Some(value) => Ok(Some(*value.get_ref())),
+            None => Ok(None),
+        };
+    let render = desc.render;
+    let image_width = at_least(&render.image_width, "image_width", 2)?;
+    let samples_per_pixel =
+        at_least(&render.samples_per_pixel, "samples_per_pixel", 1)?;
+    let max_depth = at_least(&render.max_depth, "max_depth", 1)?;
+    let aspect_ratio = match &render.aspect_ratio {
+        Some(ratio)
+            if !(ratio.get_ref().is_finite() && *ratio.get_ref() > 0.0) =>
+        {
+            return Err(builder.error(
+                ratio.span(),
+                String::from("aspect_ratio must be positive"),
+            ))
+        }
+        ratio => ratio.as_ref().map(|ratio| *ratio.get_ref()),
+    };
+
+    scene.background = render.background.map_or(scene.background, Vec3::from);
+    scene.samples_per_pixel =
+        samples_per_pixel.unwrap_or(scene.samples_per_pixel);
+    scene.max_depth = max_depth.unwrap_or(scene.max_depth);
+    scene.aspect_ratio = aspect_ratio.unwrap_or(scene.aspect_ratio);
+    scene.set_image_width(image_width.unwrap_or(scene.image_width));
+
+    Ok(scene)
+}
+
+fn is_light(
+    object: &ObjectDesc,
+    materials: &HashMap<String, MaterialDesc>,
+) -> bool {
+    match object {
+        ObjectDesc::Sphere { material, .. }
+        | ObjectDesc::XyRect { material, .. }
+        | ObjectDesc::XzRect { material, .. }
+        | ObjectDesc::YzRect { material, .. } => matches!(
+            materials.get(material.get_ref()),
+            Some(MaterialDesc::DiffuseLight { .. })
+        ),
+        ObjectDesc::Translate { object, .. }
+        | ObjectDesc::RotateY { object, .. } => is_light(object, materials),
+        _ => false,
+    }
+}
+
+struct Builder<'a> {
+    path: &'a Path,
+    source: &'a str,
+    texture_descs: &'a HashMap<String, Spanned<TextureDesc>>,
+    textures: HashMap<String, Arc<dyn Texture>>,
+    materials: HashMap<String, Arc<dyn Material>>,
+    split_method: SplitMethod,
+}
+
+impl Builder<'_> {
+    fn error(&self, span: Range<usize>, message: String) -> SceneFileError {
+        let before = &self.source[..span.start];
+        let line = before.matches('\n').count() + 1;
+        let column = before.rfind('\n').map_or(before.chars().count(), |i| {
+            before[i + 1..].chars().count()
+        }) + 1;
+
+        SceneFileError::Invalid {
+            path: self.path.to_path_buf(),
+            line,
+            column,
+            message,
+        }
+    }
+
+    // meshes loaded from files are gray unless given a material
+    fn mesh_material(
+        &self,
+        material: &Option<Spanned<String>>,
+    ) -> Result<Arc<dyn Material>, SceneFileError> {
+        match material {
+            Some(material) => self.named_material(material),
+            None => Ok(Arc::new(Lambertian::new(Arc::new(SolidColor::new(
+                Vec3::from_float(0.8),
+            ))))),
+        }
+    }
+
+    // relative paths in a scene file are relative to the file itself
+    fn dir(&self) -> &Path {
+        self.path.parent().unwrap_or(Path::new(""))
+    }
+
+    // `resolving` holds the chain of textures being built, to report cycles
+    fn named_texture(
+        &mut self,
+        name: &str,
+        resolving: &mut Vec<String>,
+    ) -> Result<Arc<dyn Texture>, SceneFileError> {
+        if let Some(texture) = self.textures.get(name) {
+            return Ok(texture.clone());
+        }
+
+        let desc = &self.texture_descs[name];
+        if resolving.iter().any(|n| n == name) {
+            resolving.push(name.to_string());
+            return Err(self.error(
+                desc.span(),
+                format!("texture cycle: {}", resolving.join(" -> ")),
+            ));
+        }
+
+        resolving.push(name.to_string());
+        let texture: Arc<dyn Texture> = match desc.get_ref() {
+            TextureDesc::Solid { color } => {
+                Arc::new(SolidColor::new(Vec3::from(*color)))
+            }
+            TextureDesc::Checker { odd, even } => {
+                let odd = self.texture(odd, resolving)?;
+                let even = self.texture(even, resolving)?;
+                Arc::new(CheckerTexture::new(odd, even))
+            }
+            TextureDesc::Noise { scale } => Arc::new(NoiseTexture::new(*scale)),
+            TextureDesc::Image { file } => {
+                let path = self.dir().join(file);
+                let texture = ImageTexture::open(&path).map_err(|err| {
+                    self.error(
+                        desc.span(),
+                        format!("{}: {}", path.display(), err),
+                    )
+                })?;
+                Arc::new(texture)
+            }
+        };
+        resolving.pop();
+
+        self.textures.insert(name.to_string(), texture.clone());
+        Ok(texture)
+    }
+
+    fn texture(
+        &mut self,
+        texture: &Spanned<TextureRef>,
+        resolving: &mut Vec<String>,
+    ) -> Result<Arc<dyn Texture>, SceneFileError> {
+        match texture.get_ref() {
+            TextureRef::Color(color) => {
+                Ok(Arc::new(SolidColor::new(Vec3::from(*color))))
+            }
+            TextureRef::Value(value) => {
+                Ok(Arc::new(SolidColor::new(Vec3::from_float(*value))))
+            }
+            TextureRef::Name(name) if self.texture_descs.contains_key(name) => {
+                self.named_texture(name, resolving)
+            }
+            TextureRef::Name(name) => Err(self
+                .error(texture.span(), format!("unknown texture `{}`", name))),
+        }
+    }
+
+    fn environment(
+        &self,
+        environment: &EnvironmentDesc,
+    ) -> Result<Box<dyn Environment>, SceneFileError> {
+        let file = &environment.file;
+        let path = self.dir().join(file.get_ref());
+        let map = EnvironmentMap::open(
+            &path,
+            environment.rotation,
+            environment.intensity,
+        )
+        .map_err(|err| {
+            self.error(file.span(), format!("{}: {}", path.display(), err))
+        })?;
+        Ok(Box::new(map))
+    }
+
+    fn sky(&self, sky: &SkyDesc) -> Result<PreethamSky, SceneFileError> {
+        let sun_direction = Vec3::from(*sky.sun_direction.get_ref());
+        if sun_direction.length_squared() <= 0.0 {
+            return Err(self.error(
+                sky.sun_direction.span(),
+                String::from("the sun direction can't be zero"),
+            ));
+        }
+
+        Ok(PreethamSky::new(
+            sun_direction,
+            sky.turbidity,
+            Vec3::from(sky.ground_albedo),
+            sky.intensity,
+        ))
+    }
+
+    fn material(
+        &mut self,
+        material: &MaterialDesc,
+    ) -> Result<Arc<dyn Material>, SceneFileError> {
+        Ok(match material {
+            MaterialDesc::Lambertian { albedo } => Arc::new(Lambertian::new(
+                self.texture(albedo, &mut Vec::new())?,
+            )),
+            MaterialDesc::OrenNayar { albedo, sigma } => Arc::new(
+                OrenNayar::new(self.texture(albedo, &mut Vec::new())?, *sigma),
+            ),
+            MaterialDesc::Metal { albedo, fuzz, film } => {
+                let metal = Metal::new(Vec3::from(*albedo), *fuzz);
+                Arc::new(match film {
+                    Some(film) => metal.with_thin_film(self.film(film)?),
+                    None => metal,
+                })
+            }
+            MaterialDesc::Conductor {
+                ior,
+                roughness,
+                film,
+            } => {
+                let roughness = self.roughness(roughness)?;
+                let conductor = match ior {
+                    ConductorIor::Metal(MetalName::Gold) => {
+                        RoughConductor::gold(roughness)
+                    }
+                    ConductorIor::Metal(MetalName::Copper) => {
+                        RoughConductor::copper(roughness)
+                    }
+                    ConductorIor::Metal(MetalName::Aluminium) => {
+                        RoughConductor::aluminium(roughness)
+                    }
+                    ConductorIor::Measured { eta, k } => RoughConductor::new(
+                        Vec3::from(*eta),
+                        Vec3::from(*k),
+                        roughness,
+                    ),
+                };
+                Arc::new(match film {
+                    Some(film) => conductor.with_thin_film(self.film(film)?),
+                    None => conductor,
+                })
+            }
+            MaterialDesc::Dielectric {
+                index_of_refraction,
+                roughness: None,
+                absorption,
+                priority,
+                film,
+            } => {
+                let dielectric =
+                    Dielectric::new(Ior::from(index_of_refraction))
+                        .with_absorption(Vec3::from(*absorption))
+                        .with_priority(*priority);
+                Arc::new(match film {
+                    Some(film) => dielectric.with_thin_film(self.film(film)?),
+                    None => dielectric,
+                })
+            }
+            MaterialDesc::Dielectric {
+                film: Some(film), ..
+            } => {
+                return Err(self.error(
+                    film.thickness.span(),
+                    String::from("a thin film needs a smooth dielectric"),
+                ))
+            }
+            MaterialDesc::Dielectric {
+                index_of_refraction,
+                roughness: Some(roughness),
+                absorption,
+                priority,
+                film: None,
+            } => Arc::new(
+                RoughDielectric::new(
+                    Ior::from(index_of_refraction),
+                    self.texture(roughness, &mut Vec::new())?,
+                )
+                .with_absorption(Vec3::from(*absorption))
+                .with_priority(*priority),
+            ),
+            MaterialDesc::Principled(desc) => {
+                let base_color = match &desc.base_color {
+                    Some(base_color) => {
+                        self.texture(base_color, &mut Vec::new())?
+                    }
+                    None => Arc::new(SolidColor::new(Vec3::from_float(0.8))),
+                };
+                let mut principled = Principled::new(base_color);
+                for (texture, desc) in [
+                    (&mut principled.metallic, &desc.metallic),
+                    (&mut principled.roughness, &desc.roughness),
+                    (&mut principled.specular, &desc.specular),
+                    (&mut principled.sheen, &desc.sheen),
+                    (&mut principled.clearcoat, &desc.clearcoat),
+                    (
+                        &mut principled.clearcoat_roughness,
+                        &desc.clearcoat_roughness,
+                    ),
+                    (&mut principled.transmission, &desc.transmission),
+                    (&mut principled.subsurface, &desc.subsurface),
+                    (&mut principled.emission, &desc.emission),
+                ] {
+                    if let Some(desc) = desc {
+                        *texture = self.texture(desc, &mut Vec::new())?;
+                    }
+                }
+                Arc::new(principled)
+            }
+            MaterialDesc::Coated {
+                base,
+                index_of_refraction,
+                roughness,
+                tint,
+                thickness,
+            } => {
+                let base = self.material(base)?;
+                let roughness = self.roughness(roughness)?;
+                Arc::new(
+                    Coated::new(base, *index_of_refraction, roughness)
+                        .with_tint(Vec3::from(*tint), *thickness),
+                )
+            }
+            MaterialDesc::DiffuseLight { emit } => Arc::new(DiffuseLight::new(
+                self.texture(emit, &mut Vec::new())?,
+            )),
+            MaterialDesc::Isotropic { albedo } => {
+                Arc::new(Isotropic::new(self.texture(albedo, &mut Vec::new())?))
+            }
+        })
+    }
+
+    fn light(
+        &self,
+        light: &LightDesc,
+    ) -> Result<Box<dyn Light>, SceneFileError> {
+        let direction = |direction: &Spanned<[f64; 3]>| {
+            let v = Vec3::from(*direction.get_ref());
+            if v.length_squared() > 0.0 {
+                Ok(v)
+            } else {
+                Err(self.error(
+                    direction.span(),
+                    String::from("a light direction can't be zero"),
+                ))
+            }
+        };
+
+        Ok(match light {
+            LightDesc::Point {
+                position,
+                intensity,
+            } => Box::new(PointLight::new(
+                Vec3::from(*position),
+                Vec3::from(*intensity),
+            )),
+            LightDesc::Spot {
+                position,
+                direction: spot_direction,
+                intensity,
+                inner_angle,
+                outer_angle,
+            } => Box::new(SpotLight::new(
+                Vec3::from(*position),
+                direction(spot_direction)?,
+                Vec3::from(*intensity),
+                *inner_angle,
+                *outer_angle,
+            )),
+            LightDesc::Directional {
+                direction: sun_direction,
+                irradiance,
+                angular_diameter,
+            } => Box::new(DirectionalLight::new(
+                direction(sun_direction)?,
+                Vec3::from(*irradiance),
+                *angular_diameter,
+            )),
+        })
+    }
+
+    // smooth when not given
+    fn film(&mut self, film: &FilmDesc) -> Result<ThinFilm, SceneFileError> {
+        Ok(ThinFilm::new(
+            self.texture(&film.thickness, &mut Vec::new())?,
+            film.index_of_refraction,
+        ))
+    }
+
+    fn roughness(
+        &mut self,
+        roughness: &Option<Spanned<TextureRef>>,
+    ) -> Result<Arc<dyn Texture>, SceneFileError> {
+        match roughness {
+            Some(roughness) => self.texture(roughness, &mut Vec::new()),
+            None => Ok(Arc::new(SolidColor::new(Vec3::ZERO))),
+        }
+    }
+
+    fn named_material(
+        &self,
+        name: &Spanned<String>,
+    ) -> Result<Arc<dyn Material>, SceneFileError> {
+        self.materials.get(name.get_ref()).cloned().ok_or_else(|| {
+            self.error(
+                name.span(),
+                format!("unknown material `{}`", name.get_ref()),
+            )
+        })
+    }
+
+    fn object(
+        &mut self,
+        object: &ObjectDesc,
+    ) -> Result<Box<dyn Hittable>, SceneFileError> {
+        Ok(match object {
+            ObjectDesc::Sphere {
+                center,
+                radius,
+                material,
+            } => Box::new(Sphere::new(
+                Vec3::from(*center),
+                *radius,
+                self.named_material(material)?,
+            )),
+            ObjectDesc::MovingSphere {
+                center0,
+                center1,
+                time0,
+                time1,
+                radius,
+                material,
+            } => Box::new(MovingSphere::new(
+                Vec3::from(*center0),
+                Vec3::from(*center1),
+                *time0,
+                *time1,
+                *radius,
+                self.named_material(material)?,
+            )),
+            ObjectDesc::XyRect {
+                x0,
+                x1,
+                y0,
+                y1,
+                k,
+                material,
+            } => Box::new(XYRect::new(
+                *x0,
+                *x1,
+                *y0,
+                *y1,
+                *k,
+                self.named_material(material)?,
+            )),
+            ObjectDesc::XzRect {
+                x0,
+                x1,
+                z0,
+                z1,
+                k,
+                material,
+            } => Box::new(XZRect::new(
+                *x0,
+                *x1,
+                *z0,
+                *z1,
+                *k,
+                self.named_material(material)?,
+            )),
+            ObjectDesc::YzRect {
+                y0,
+                y1,
+                z0,
+                z1,
+                k,
+                material,
+            } => Box::new(YZRect::new(
+                *y0,
+                *y1,
+                *z0,
+                *z1,
+                *k,
+                self.named_material(material)?,
+            )),
+            ObjectDesc::Box { min, max, material } => Box::new(Box3::new(
+                Vec3::from(*min),
+                Vec3::from(*max),
+                self.named_material(material)?,
+            )),
+            ObjectDesc::Triangle {
+                v0,
+                v1,
+                v2,
+                material,
+            } => Box::new(Triangle::new(
+                Vec3::from(*v0),
+                Vec3::from(*v1),
+                Vec3::from(*v2),
+                self.named_material(material)?,
+            )),
+            ObjectDesc::Mesh {
+                positions,
+                normals,
+                uvs,
+                indices,
+                material,
+            } => {
+                Box::new(
+                    TriangleMesh::new(
+                        positions.iter().copied().map(Vec3::from).collect(),
+                        normals.as_ref().map(|n| {
+                            n.get_ref()
+                                .iter()
+                                .copied()
+                                .map(Vec3::from)
+                                .collect()
+                        }),
+                        uvs.as_ref().map(|uv| {
+                            uv.get_ref().iter().map(|&[u, v]| (u, v)).collect()
+                        }),
+                        None,
+                        indices.get_ref().clone(),
+                        self.named_material(material)?,
+                    )
+                    .map_err(|err| {
+                        // a wrong count is reported on what is miscounted
+                        let span = match err {
+                            MeshError::VertexCount("normals", _) => {
+                                normals.as_ref().map(Spanned::span)
+                            }
+                            MeshError::VertexCount("uvs", _) => {
+                                uvs.as_ref().map(Spanned::span)
+                            }
+                            _ => None,
+                        };
+                        self.error(
+                            span.unwrap_or_else(|| indices.span()),
+                            err.to_string(),
+                        )
+                    })?,
+                )
+            }
+            ObjectDesc::Obj { file, material } => {
+                // faces without an MTL material fall back to `material`
+                let material = self.mesh_material(material)?;
+                let path = self.dir().join(file.get_ref());
+                let objects = obj::load(&path, material)
+                    .map_err(|err| self.error(file.span(), err.to_string()))?;
+                Box::new(Bvh::with_split_method(
+                    objects,
+                    0.0,
+                    1.0,
+                    self.split_method,
+                ))
+            }
+            ObjectDesc::Ply { file, material } => {
+                // without a material the vertex colors are used
+                let material = match material {
+                    Some(material) => Some(self.named_material(material)?),
+                    None => None,
+                };
+                let path = self.dir().join(file.get_ref());
+                Box::new(
+                    ply::load(&path, material).map_err(|err| {
+                        self.error(file.span(), err.to_string())
+                    })?,
+                )
+            }
+            ObjectDesc::Stl { file, material } => {
+                let material = self.mesh_material(material)?;
+                let path = self.dir().join(file.get_ref());
+                Box::new(
+                    stl::load(&path, material).map_err(|err| {
+                        self.error(file.span(), err.to_string())
+                    })?,
+                )
+            }
+            ObjectDesc::ConstantMedium {
+                boundary,
+                density,
+                albedo,
+            } => Box::new(ConstantMedium::new(
+                self.object(boundary)?,
+                *density,
+                self.texture(albedo, &mut Vec::new())?,
+            )),
+            ObjectDesc::Bvh { objects } => {
+                if objects.get_ref().is_empty() {
+                    return Err(self.error(
+                        objects.span(),
+                        String::from("a bvh needs at least one object"),
+                    ));
+                }
+                let objects = objects
+                    .get_ref()
+                    .iter()
+                    .map(|object| self.object(object))
+                    .collect::<Result<World, _>>()?;
+                Box::new(Bvh::with_split_method(
+                    objects,
+                    0.0,
+                    1.0,
+                    self.split_method,
+                ))
+            }
+            ObjectDesc::Translate { offset, object } => Box::new(
+                Translate::new(self.object(object)?, Vec3::from(*offset)),
+            ),
+            ObjectDesc::RotateY { angle, object } => {
+                Box::new(RotateY::new(self.object(object)?, *angle))
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{error, fixture};
+
+    #[test]
+    fn loads_a_scene() {
+        let scene = load(&fixture("small_scene.toml")).unwrap();
+
+        assert_eq!(scene.lookfrom, Vec3::new(0.0, 1.0, 5.0));
+        assert_eq!(scene.lookat, Vec3::new(0.0, 0.5, 0.0));
+        assert_eq!(scene.vfov, 30.0);
+        assert_eq!(scene.image_width, 32);
+        assert_eq!(scene.image_height, 16);
+        assert_eq!(scene.samples_per_pixel, 8);
+        assert_eq!(scene.max_depth, 5);
+        assert_eq!(scene.background, Vec3::from_float(0.1));
+        assert_eq!(scene.world.len(), 3);
+    }
+
+    #[test]
+    fn loads_the_bundled_scenes() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes");
+        for entry in dir.read_dir().unwrap() {
+            let path = entry.unwrap().path();
+            if let Err(err) = load(&path) {
+                panic!("{}", err);
+            }
+        }
+    }
+
+    #[test]
+    fn reports_where_a_name_is_unknown() {
+        let path = fixture("unknown_texture.toml");
+        assert_eq!(
+            error(load(&path)),
+            format!("{}:3:36: unknown texture `marble`", path.display())
+        );
+
+        let path = fixture("unknown_material.toml");
+        assert_eq!(
+            error(load(&path)),
+            format!("{}:8:63: unknown material `grey`", path.display())
+        );
+    }
+
+    #[test]
+    fn reports_bad_meshes_on_what_is_wrong() {
+        let path = fixture("bad_mesh_index.toml");
+        assert_eq!(
+            error(load(&path)),
+            format!(
+                "{}:5:85: index 3 out of range for 3 positions",
+                path.display()
+            )
+        );
+
+        let path = fixture("bad_mesh_uvs.toml");
+        assert_eq!(
+            error(load(&path)),
+            format!("{}:5:81: expected 3 uvs", path.display())
+        );
+    }
+
+    #[test]
+    fn reports_render_settings_out_of_range() {
+        let path = fixture("bad_render.toml");
+        assert_eq!(
+            error(load(&path)),
+            format!(
+                "{}:3:21: samples_per_pixel must be at least 1",
+                path.display()
+            )
+        );
+
+        let path = fixture("bad_render_width.toml");
+        assert_eq!(
+            error(load(&path)),
+            format!("{}:3:15: image_width must be at least 2", path.display())
+        );
+    }
+
+    #[test]
+    fn reports_texture_cycles() {
+        let path = fixture("texture_cycle.toml");
+        let message = error(load(&path));
+        assert!(
+            message.ends_with("texture cycle: a -> b -> a"),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn reports_syntax_and_io_errors() {
+        let path = fixture("missing.toml");
+        let message = error(load(&path));
+        assert!(message.starts_with(&*path.to_string_lossy()), "{}", message);
+
+        let path = fixture("unknown_field.toml");
+        let message = error(load(&path));
+        assert!(message.starts_with(&*path.to_string_lossy()), "{}", message);
+        assert!(message.contains("unknown field `radius`"), "{}", message);
+    }
+}
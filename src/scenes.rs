@@ -3,9 +3,11 @@ use std::sync::Arc;
 use crate::{
     aarect::{XYRect, XZRect, YZRect},
     box3::Box3,
-    bvh::BvhNode,
+    bvh::{Bvh, SplitMethod},
     constant_medium::ConstantMedium,
+    environment::Environment,
     hit::{Hittable, RotateY, Translate, World},
+    light::Light,
     material::{Dielectric, DiffuseLight, Lambertian, Metal},
     moving_sphere::MovingSphere,
     sphere::Sphere,
@@ -16,6 +18,16 @@ use rand::Rng;
 
 pub struct Scene {
     pub world: World,
+    // emissive shapes sampled for direct light, duplicates of objects in
+    // `world`. Emitters missing here are only found by bounces
+    pub lights: World,
+    // point, spot and directional lights, which only light the scene through
+    // explicit sampling
+    pub punctual_lights: Vec<Box<dyn Light>>,
+    // replaces `background` for rays leaving the scene
+    pub environment: Option<Box<dyn Environment>>,
+    // camera rays see `background` instead of the environment when unset
+    pub environment_visible: bool,
     pub background: Vec3,
     pub lookfrom: Vec3,
     pub lookat: Vec3,
@@ -30,21 +42,7 @@ pub struct Scene {
     pub dist_to_focus: f64,
 }
 
-impl Scene {
-    pub fn new(n: usize) -> Self {
-        match n {
-            1 => Self::random_scene(),
-            2 => Self::two_spheres(),
-            3 => Self::two_perlin_spheres(),
-            4 => Self::earth(),
-            5 => Self::simple_light(),
-            6 => Self::cornell_box(),
-            7 => Self::cornell_smoke(),
-            8 => Self::final_scene(),
-            _ => Self::final_scene(),
-        }
-    }
-
+impl Default for Scene {
     fn default() -> Self {
         let aspect_ratio = 16.0 / 9.0;
         let image_width: u64 = 400;
@@ -52,6 +50,10 @@ impl Scene {
 
         Scene {
             world: World::new(),
+            lights: World::new(),
+            punctual_lights: Vec::new(),
+            environment: None,
+            environment_visible: true,
             background: Color::new(0.70, 0.80, 1.00),
             lookfrom: Point3::new(13.0, 2.0, 3.0),
             lookat: Point3::ZERO,
@@ -66,6 +68,52 @@ impl Scene {
             dist_to_focus: 10.0,
         }
     }
+}
+
+impl Scene {
+    pub const NAMES: [&'static str; 8] = [
+        "random_scene",
+        "two_spheres",
+        "two_perlin_spheres",
+        "earth",
+        "simple_light",
+        "cornell_box",
+        "cornell_smoke",
+        "final_scene",
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::from_name_with_split_method(name, SplitMethod::Sah)
+    }
+
+    // Builds the BVHs of the scene with `split_method`
+    pub fn from_name_with_split_method(
+        name: &str,
+        split_method: SplitMethod,
+    ) -> Option<Self> {
+        match name {
+            "random_scene" => Some(Self::random_scene()),
+            "two_spheres" => Some(Self::two_spheres()),
+            "two_perlin_spheres" => Some(Self::two_perlin_spheres()),
+            "earth" => Some(Self::earth()),
+            "simple_light" => Some(Self::simple_light()),
+            "cornell_box" => Some(Self::cornell_box()),
+            "cornell_smoke" => Some(Self::cornell_smoke()),
+            "final_scene" => Some(Self::final_scene(split_method)),
+            _ => None,
+        }
+    }
+
+    pub fn set_image_width(&mut self, image_width: u64) {
+        self.image_width = image_width;
+        self.image_height =
+            (((image_width as f64) / self.aspect_ratio) as u64).max(2);
+    }
+
+    pub fn set_aspect_ratio(&mut self, aspect_ratio: f64) {
+        self.aspect_ratio = aspect_ratio;
+        self.set_image_width(self.image_width);
+    }
 
     fn default_cornell() -> Self {
         Scene {
@@ -244,7 +292,7 @@ impl Scene {
     }
 
     fn simple_light() -> Self {
-        let world: World = {
+        let (world, light) = {
             let mut world = World::new();
             let pertext = Arc::new(NoiseTexture::new(4.0));
             world.push(Box::new(Sphere::new(
@@ -260,20 +308,15 @@ impl Scene {
 
             let diffuse_light =
                 Arc::new(DiffuseLight::from_color(Color::from_float(4.0)));
-            world.push(Box::new(XYRect::new(
-                3.0,
-                5.0,
-                1.0,
-                3.0,
-                -2.0,
-                diffuse_light,
-            )));
+            let light = XYRect::new(3.0, 5.0, 1.0, 3.0, -2.0, diffuse_light);
+            world.push(Box::new(light.clone()));
 
-            world
+            (world, light)
         };
 
         Scene {
             world,
+            lights: vec![Box::new(light)],
             samples_per_pixel: 400,
             background: Color::ZERO,
             lookfrom: Point3::new(26.0, 3.0, 26.0),
@@ -284,7 +327,7 @@ impl Scene {
     }
 
     fn cornell_box() -> Self {
-        let world: World = {
+        let (world, light) = {
             let mut world = World::new();
             let red = Arc::new(Lambertian::from_solid_color(Color::new(
                 0.65, 0.05, 0.05,
@@ -314,14 +357,8 @@ impl Scene {
                 0.0,
                 red.clone(),
             )));
-            world.push(Box::new(XZRect::new(
-                213.0,
-                343.0,
-                227.0,
-                332.0,
-                554.0,
-                light.clone(),
-            )));
+            let light = XZRect::new(213.0, 343.0, 227.0, 332.0, 554.0, light);
+            world.push(Box::new(light.clone()));
             world.push(Box::new(XZRect::new(
                 0.0,
                 555.0,
@@ -365,17 +402,18 @@ impl Scene {
             box2 = Box::new(Translate::new(box2, Vec3::new(130.0, 0.0, 65.0)));
             world.push(box2);
 
-            world
+            (world, light)
         };
 
         Scene {
             world,
+            lights: vec![Box::new(light)],
             ..Self::default_cornell()
         }
     }
 
     fn cornell_smoke() -> Self {
-        let world: World = {
+        let (world, light) = {
             let mut world = World::new();
             let red = Arc::new(Lambertian::from_solid_color(Color::new(
                 0.65, 0.05, 0.05,
@@ -405,14 +443,8 @@ impl Scene {
                 0.0,
                 red.clone(),
             )));
-            world.push(Box::new(XZRect::new(
-                113.0,
-                443.0,
-                127.0,
-                432.0,
-                554.0,
-                light.clone(),
-            )));
+            let light = XZRect::new(113.0, 443.0, 127.0, 432.0, 554.0, light);
+            world.push(Box::new(light.clone()));
             world.push(Box::new(XZRect::new(
                 0.0,
                 555.0,
@@ -464,17 +496,18 @@ impl Scene {
                 Color::ONE,
             )));
 
-            world
+            (world, light)
         };
 
         Scene {
             world,
+            lights: vec![Box::new(light)],
             ..Self::default_cornell()
         }
     }
 
-    fn final_scene() -> Self {
-        let world: World = {
+    fn final_scene(split_method: SplitMethod) -> Self {
+        let (world, light) = {
             let mut world = World::new();
 
             let ground = Arc::new(Lambertian::from_solid_color(Color::new(
@@ -521,14 +554,17 @@ impl Scene {
             }
 
             // world.extend(boxes1);
-            world.push(Box::new(BvhNode::new(boxes1, 0.0, 1.0)));
-            // world.push(Box::new(BvhNode::new(boxes1, 0.0, 1.0)));
+            world.push(Box::new(Bvh::with_split_method(
+                boxes1,
+                0.0,
+                1.0,
+                split_method,
+            )));
 
             let light =
                 Arc::new(DiffuseLight::from_color(Color::from_float(7.0)));
-            world.push(Box::new(XZRect::new(
-                123.0, 423.0, 147.0, 412.0, 554.0, light,
-            )));
+            let light = XZRect::new(123.0, 423.0, 147.0, 412.0, 554.0, light);
+            world.push(Box::new(light.clone()));
 
             let center1 = Point3::new(400.0, 400.0, 200.0);
             let center2 = center1 + Vec3::from_x(30.0);
@@ -609,17 +645,23 @@ impl Scene {
 
             world.push(Box::new(Translate::new(
                 Box::new(RotateY::new(
-                    Box::new(BvhNode::new(boxes2, 0.0, 1.0)),
+                    Box::new(Bvh::with_split_method(
+                        boxes2,
+                        0.0,
+                        1.0,
+                        split_method,
+                    )),
                     15.0,
                 )),
                 Vec3::new(-100.0, 270.0, 395.0),
             )));
 
-            world
+            (world, light)
         };
 
         Scene {
             world,
+            lights: vec![Box::new(light)],
             aspect_ratio: 1.0,
             image_width: 400,
             image_height: 400,
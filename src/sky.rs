@@ -0,0 +1,279 @@
+use std::f64::consts::PI;
+
+use crate::{
+    environment::{Environment, EnvironmentMap},
+    light::DirectionalLight,
+    vec3::{Color, Vec3},
+};
+
+// The model works in kcd/m² and klux, divided by this so that a white surface
+// facing a high sun comes out around 1
+const PHOTOMETRIC_UNIT: f64 = 30.0;
+
+// Illuminance of the sun above the atmosphere, in klux
+const SOLAR_ILLUMINANCE: f64 = 128.0;
+
+// As seen from the ground, in degrees
+const SUN_ANGULAR_DIAMETER: f64 = 0.53;
+
+// Preetham, Shirley and Smits' analytic daylight ("A Practical Analytic Model
+// for Daylight", 1999). +y is up and `sun_direction` points towards the sun.
+// Below the horizon is a diffuse ground of `ground_albedo` lit by the sky and
+// the sun, the sun itself is a separate light, see `sun`
+pub struct PreethamSky {
+    dome: Dome,
+    ground: Color,
+    sun: Color,
+    intensity: f64,
+    // the sky tabulated for sampling
+    map: EnvironmentMap,
+}
+
+impl PreethamSky {
+    pub fn new(
+        sun_direction: Vec3,
+        turbidity: f64,
+        ground_albedo: Color,
+        intensity: f64,
+    ) -> Self {
+        let to_sun = sun_direction.normalized();
+        // the fit was made for these turbidities
+        let turbidity = turbidity.clamp(2.0, 10.0);
+
+        let dome = Dome::new(to_sun, turbidity);
+        let sun = sun_irradiance(to_sun, turbidity);
+        let ground = ground_albedo
+            * (dome.irradiance() + to_sun.y().max(0.0) * sun)
+            / PI;
+        let map = EnvironmentMap::from_fn(128, 64, |direction| {
+            if direction.y() < 0.0 {
+                ground
+            } else {
+                dome.radiance(direction)
+            }
+        });
+
+        Self {
+            dome,
+            ground,
+            sun,
+            intensity,
+            map,
+        }
+    }
+
+    // A directional light for the sun disk, matching the sky
+    pub fn sun(&self) -> DirectionalLight {
+        DirectionalLight::new(
+            -self.dome.to_sun,
+            self.intensity * self.sun,
+            SUN_ANGULAR_DIAMETER,
+        )
+    }
+}
+
+impl Environment for PreethamSky {
+    fn radiance(&self, direction: &Vec3) -> Color {
+        if direction.y() < 0.0 {
+            self.intensity * self.ground
+        } else {
+            self.intensity * self.dome.radiance(direction)
+        }
+    }
+
+    fn pdf_value(&self, direction: &Vec3) -> f64 {
+        self.map.pdf_value(direction)
+    }
+
+    fn random(&self) -> Vec3 {
+        self.map.random()
+    }
+}
+
+// The sky above the horizon
+struct Dome {
+    to_sun: Vec3,
+    // Perez coefficients A to E of the luminance Y and the chromaticities x
+    // and y, and their zenith values over the Perez function there
+    perez: [[f64; 5]; 3],
+    zenith: [f64; 3],
+}
+
+impl Dome {
+    fn new(to_sun: Vec3, t: f64) -> Self {
+        // the sky stays as at sunset once the sun goes down
+        let theta_sun = to_sun.y().clamp(0.0, 1.0).acos();
+
+        let perez = [
+            [
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ],
+            [
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ],
+            [
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ],
+        ];
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_sun);
+        let zenith_luminance =
+            (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let zenith_x = zenith_chromaticity(
+            [
+                [0.00166, -0.00375, 0.00209, 0.0],
+                [-0.02903, 0.06377, -0.03202, 0.00394],
+                [0.11693, -0.21196, 0.06052, 0.25886],
+            ],
+            t,
+            theta_sun,
+        );
+        let zenith_y = zenith_chromaticity(
+            [
+                [0.00275, -0.00610, 0.00317, 0.0],
+                [-0.04214, 0.08970, -0.04153, 0.00516],
+                [0.15346, -0.26756, 0.06670, 0.26688],
+            ],
+            t,
+            theta_sun,
+        );
+
+        let zenith = [zenith_luminance.max(0.0), zenith_x, zenith_y];
+        let zenith = std::array::from_fn(|i| {
+            zenith[i] / perez_function(&perez[i], 1.0, theta_sun)
+        });
+
+        Self {
+            to_sun,
+            perez,
+            zenith,
+        }
+    }
+
+    fn radiance(&self, direction: &Vec3) -> Color {
+        let gamma = direction.dot(self.to_sun).clamp(-1.0, 1.0).acos();
+        let [luminance, x, y]: [f64; 3] = std::array::from_fn(|i| {
+            self.zenith[i]
+                * perez_function(&self.perez[i], direction.y(), gamma)
+        });
+        if y <= 0.0 {
+            return Color::ZERO;
+        }
+
+        // xyY to linear sRGB
+        let big_x = x / y * luminance;
+        let big_z = (1.0 - x - y) / y * luminance;
+        let rgb = Color::new(
+            3.2406 * big_x - 1.5372 * luminance - 0.4986 * big_z,
+            -0.9689 * big_x + 1.8758 * luminance + 0.0415 * big_z,
+            0.0557 * big_x - 0.2040 * luminance + 1.0570 * big_z,
+        );
+        Color::new(rgb.x().max(0.0), rgb.y().max(0.0), rgb.z().max(0.0))
+            / PHOTOMETRIC_UNIT
+    }
+
+    // Light on the ground, midpoint rule over the hemisphere
+    fn irradiance(&self) -> Color {
+        let (rows, columns) = (32, 64);
+        let mut irradiance = Color::ZERO;
+        for j in 0..rows {
+            let theta = PI / 2.0 * (j as f64 + 0.5) / rows as f64;
+            let solid_angle = PI / 2.0 / rows as f64 * 2.0 * PI
+                / columns as f64
+                * theta.sin();
+            for i in 0..columns {
+                let phi = 2.0 * PI * (i as f64 + 0.5) / columns as f64;
+                let direction = Vec3::new(
+                    theta.sin() * phi.cos(),
+                    theta.cos(),
+                    theta.sin() * phi.sin(),
+                );
+                irradiance +=
+                    self.radiance(&direction) * theta.cos() * solid_angle;
+            }
+        }
+        irradiance
+    }
+}
+
+// Relative distribution of sky light by the cosine of the angle to the zenith
+// and the angle gamma to the sun
+fn perez_function(coefficients: &[f64; 5], cos_theta: f64, gamma: f64) -> f64 {
+    let [a, b, c, d, e] = *coefficients;
+    (1.0 + a * (b / cos_theta).exp())
+        * (1.0 + c * (d * gamma).exp() + e * gamma.cos().powi(2))
+}
+
+fn zenith_chromaticity(matrix: [[f64; 4]; 3], t: f64, theta_sun: f64) -> f64 {
+    let ts = [t * t, t, 1.0];
+    let thetas = [theta_sun.powi(3), theta_sun.powi(2), theta_sun, 1.0];
+    (0..3)
+        .map(|i| ts[i] * (0..4).map(|j| matrix[i][j] * thetas[j]).sum::<f64>())
+        .sum()
+}
+
+// Sunlight through the air, with Rayleigh and aerosol extinction at roughly
+// red, green and blue wavelengths
+fn sun_irradiance(to_sun: Vec3, turbidity: f64) -> Color {
+    if to_sun.y() <= 0.0 {
+        return Color::ZERO;
+    }
+
+    // relative air mass, Kasten and Young's fit near the horizon
+    let elevation = to_sun.y().asin().to_degrees();
+    let air_mass =
+        1.0 / (to_sun.y() + 0.50572 * (elevation + 6.07995).powf(-1.6364));
+
+    let beta = 0.04608 * turbidity - 0.04586;
+    let transmittance = |wavelength: f64| {
+        let rayleigh = 0.008735 * wavelength.powf(-4.08);
+        let aerosol = beta * wavelength.powf(-1.3);
+        (-air_mass * (rayleigh + aerosol)).exp()
+    };
+
+    SOLAR_ILLUMINANCE / PHOTOMETRIC_UNIT
+        * Color::new(
+            transmittance(0.680),
+            transmittance(0.550),
+            transmittance(0.440),
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn low_sun_is_dimmer_and_redder() {
+        let high = sun_irradiance(Vec3::new(0.0, 1.0, 0.2).normalized(), 3.0);
+        let low = sun_irradiance(Vec3::new(0.0, 0.1, 1.0).normalized(), 3.0);
+        assert!(low.y() < high.y());
+        assert!(low.z() / low.x() < high.z() / high.x());
+        assert_eq!(sun_irradiance(Vec3::new(0.0, -0.1, 1.0), 3.0), Color::ZERO);
+    }
+
+    #[test]
+    fn zenith_is_blue_and_ground_is_lit() {
+        let sky = PreethamSky::new(
+            Vec3::new(1.0, 1.0, 0.0),
+            3.0,
+            Color::from_float(0.3),
+            1.0,
+        );
+        let zenith = sky.radiance(&Vec3::from_y(1.0));
+        assert!(zenith.z() > zenith.x());
+        assert!(sky.radiance(&Vec3::from_y(-1.0)).y() > 0.0);
+    }
+}
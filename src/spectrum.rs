@@ -0,0 +1,198 @@
+use rand::Rng;
+
+use crate::vec3::Color;
+
+// Range the wavelengths are picked in, in nm
+const WAVELENGTH_MIN: f64 = 380.0;
+const WAVELENGTH_MAX: f64 = 780.0;
+
+// Integral of the y matching function over the range, so that a flat
+// spectrum of 1 has a luminance of 1
+const Y_INTEGRAL: f64 = 106.92;
+
+// Linear sRGB of a flat spectrum of 1, divided out so that it's white
+const FLAT_RGB: [f64; 3] = [1.2006, 0.9498, 0.9077];
+
+// The three wavelengths a spectral path carries in the channels of its
+// `Color`s: the hero, picked at random, and two more spread evenly over the
+// range from it (Wilkie et al., "Hero Wavelength Spectral Sampling", 2014)
+#[derive(Clone, Copy, Debug)]
+pub struct Wavelengths([f64; 3]);
+
+impl Wavelengths {
+    pub fn random() -> Self {
+        let range = WAVELENGTH_MAX - WAVELENGTH_MIN;
+        let hero = rand::thread_rng().gen::<f64>() * range;
+        Self(std::array::from_fn(|i| {
+            WAVELENGTH_MIN + (hero + i as f64 * range / 3.0) % range
+        }))
+    }
+
+    // the one materials that bend light differently by wavelength follow
+    pub fn hero(&self) -> f64 {
+        self.0[0]
+    }
+
+    // An RGB color of the scene at each wavelength
+    pub fn upsample(self, rgb: Color) -> Color {
+        Color::new(
+            smits(rgb, self.0[0]),
+            smits(rgb, self.0[1]),
+            smits(rgb, self.0[2]),
+        )
+    }
+
+    // Linear sRGB of the radiance `values` at each wavelength, through XYZ
+    pub fn to_rgb(self, values: Color) -> Color {
+        let mut xyz = [0.0; 3];
+        for (i, &wavelength) in self.0.iter().enumerate() {
+            let matching = color_matching(wavelength);
+            for c in 0..3 {
+                xyz[c] += values[i] * matching[c];
+            }
+        }
+
+        // each wavelength is picked with a density of 1 over the range
+        xyz_to_rgb(xyz.map(|v| v * (WAVELENGTH_MAX - WAVELENGTH_MIN) / 3.0))
+    }
+}
+
+// Linear sRGB of a surface reflecting `reflectance(wavelength)` of white
+// light, for materials whose color comes from a spectrum
+pub fn reflectance_rgb(reflectance: impl Fn(f64) -> f64) -> Color {
+    // midpoint rule in 10 nm steps
+    let steps = 40;
+    let step = (WAVELENGTH_MAX - WAVELENGTH_MIN) / steps as f64;
+    let mut xyz = [0.0; 3];
+    for i in 0..steps {
+        let wavelength = WAVELENGTH_MIN + (i as f64 + 0.5) * step;
+        let value = reflectance(wavelength);
+        let matching = color_matching(wavelength);
+        for c in 0..3 {
+            xyz[c] += value * matching[c] * step;
+        }
+    }
+    xyz_to_rgb(xyz)
+}
+
+// From XYZ integrated over the range, with a flat spectrum coming out white
+fn xyz_to_rgb(xyz: [f64; 3]) -> Color {
+    let [x, y, z] = xyz.map(|v| v / Y_INTEGRAL);
+    Color::new(
+        (3.2406 * x - 1.5372 * y - 0.4986 * z) / FLAT_RGB[0],
+        (-0.9689 * x + 1.8758 * y + 0.0415 * z) / FLAT_RGB[1],
+        (0.0557 * x - 0.2040 * y + 1.0570 * z) / FLAT_RGB[2],
+    )
+}
+
+// Smits' spectra for white and the six primary and secondary colors ("An RGB
+// to Spectrum Conversion for Reflectances", 1999), in 10 bins from 380 to
+// 720 nm
+const WHITE: [f64; 10] = [
+    1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000,
+    1.0000,
+];
+const CYAN: [f64; 10] = [
+    0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000,
+    0.0000,
+];
+const MAGENTA: [f64; 10] = [
+    1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000,
+    0.9959,
+];
+const YELLOW: [f64; 10] = [
+    0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685,
+    0.9840,
+];
+const RED: [f64; 10] = [
+    0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149,
+    1.0149,
+];
+const GREEN: [f64; 10] = [
+    0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000,
+    0.0025,
+];
+const BLUE: [f64; 10] = [
+    1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483,
+    0.0496,
+];
+
+// Value at `wavelength` of a smooth spectrum for `rgb`: white for the
+// smallest channel, then the secondary and the primary color the other two
+// share
+fn smits(rgb: Color, wavelength: f64) -> f64 {
+    // past 720 nm the last bin carries on
+    let bin = ((wavelength - 380.0) / 34.0).clamp(0.0, 9.0) as usize;
+    let (r, g, b) = (rgb.x(), rgb.y(), rgb.z());
+
+    if r <= g && r <= b {
+        r * WHITE[bin]
+            + if g <= b {
+                (g - r) * CYAN[bin] + (b - g) * BLUE[bin]
+            } else {
+                (b - r) * CYAN[bin] + (g - b) * GREEN[bin]
+            }
+    } else if g <= r && g <= b {
+        g * WHITE[bin]
+            + if r <= b {
+                (r - g) * MAGENTA[bin] + (b - r) * BLUE[bin]
+            } else {
+                (b - g) * MAGENTA[bin] + (r - b) * RED[bin]
+            }
+    } else {
+        b * WHITE[bin]
+            + if r <= g {
+                (r - b) * YELLOW[bin] + (g - r) * GREEN[bin]
+            } else {
+                (g - b) * YELLOW[bin] + (r - g) * RED[bin]
+            }
+    }
+}
+
+// CIE 1931 color matching functions, Wyman, Sloan and Shirley's piecewise
+// Gaussian fit ("Simple Analytic Approximations to the CIE XYZ Color Matching
+// Functions", 2013)
+fn color_matching(wavelength: f64) -> [f64; 3] {
+    let g = |mean: f64, below: f64, above: f64| {
+        let sigma = if wavelength < mean { below } else { above };
+        (-0.5 * ((wavelength - mean) / sigma).powi(2)).exp()
+    };
+
+    [
+        1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7)
+            - 0.065 * g(501.1, 20.4, 26.2),
+        0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1),
+        1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colors_come_back_from_their_spectrum() {
+        // the hero spread evenly over the range, the midpoint rule
+        let n = 3000;
+        let range = WAVELENGTH_MAX - WAVELENGTH_MIN;
+        for rgb in [
+            Color::ONE,
+            Color::new(1.0, 0.0, 0.0),
+            Color::new(0.0, 1.0, 0.0),
+            Color::new(0.0, 0.0, 1.0),
+            Color::new(0.8, 0.2, 0.1),
+            Color::new(2.0, 4.0, 3.0),
+        ] {
+            let mut sum = Color::ZERO;
+            for k in 0..n {
+                let hero = (k as f64 + 0.5) / n as f64 * range;
+                let wavelengths = Wavelengths(std::array::from_fn(|i| {
+                    WAVELENGTH_MIN + (hero + i as f64 * range / 3.0) % range
+                }));
+                sum += wavelengths.to_rgb(wavelengths.upsample(rgb));
+            }
+            let difference = sum / n as f64 - rgb;
+            assert!(difference.length() < 0.03, "{:?} {:?}", rgb, difference);
+        }
+    }
+}
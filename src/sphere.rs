@@ -1,9 +1,12 @@
 use std::{f64::consts::PI, sync::Arc};
 
+use rand::Rng;
+
 use crate::{
     aabb::AABB,
     hit::{HitRecord, Hittable},
     material::Material,
+    ray::Ray,
     vec3::{Point3, Vec3},
 };
 
@@ -27,6 +30,17 @@ impl Sphere {
         }
     }
 
+    // cosine of the half angle of the cone the sphere covers seen from
+    // `origin`, None from inside
+    fn cos_theta_max(&self, origin: &Point3) -> Option<f64> {
+        let distance_squared = (self.center - *origin).length_squared();
+        let radius_squared = self.radius * self.radius;
+        if distance_squared <= radius_squared {
+            return None;
+        }
+        Some((1.0 - radius_squared / distance_squared).sqrt())
+    }
+
     pub fn get_sphere_uv(p: &Point3) -> (f64, f64) {
         let theta = (-p.y()).acos();
         let phi = (-p.z()).atan2(p.x()) + PI;
@@ -73,6 +87,8 @@ impl Hittable for Sphere {
             normal,
             mat: self.material.clone(),
             front_face: false,
+            outside_index_of_refraction: 1.0,
+            vertex_color: None,
         };
 
         let outward_normal = (rec.p - self.center) / self.radius;
@@ -90,4 +106,32 @@ impl Hittable for Sphere {
             self.center + Vec3::from_float(self.radius),
         ))
     }
+
+    // uniform over the cone of directions the sphere covers
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        let ray = Ray::new(*origin, *direction, 0.0);
+        if self.hit(&ray, 0.001, f64::INFINITY).is_none() {
+            return 0.0;
+        }
+
+        match self.cos_theta_max(origin) {
+            Some(cos_theta_max) => 1.0 / (2.0 * PI * (1.0 - cos_theta_max)),
+            None => 0.0,
+        }
+    }
+
+    fn random(&self, origin: &Point3) -> Vec3 {
+        let Some(cos_theta_max) = self.cos_theta_max(origin) else {
+            return Vec3::from_x(1.0);
+        };
+
+        let mut rng = rand::thread_rng();
+        let z = 1.0 + rng.gen::<f64>() * (cos_theta_max - 1.0);
+        let phi = 2.0 * PI * rng.gen::<f64>();
+        let sin_theta = (1.0 - z * z).max(0.0).sqrt();
+
+        let w = (self.center - *origin).normalized();
+        let (t, b) = w.orthonormal_basis();
+        phi.cos() * sin_theta * t + phi.sin() * sin_theta * b + z * w
+    }
 }
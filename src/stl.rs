@@ -0,0 +1,101 @@
+use std::{
+    error::Error,
+    fmt::{self, Display},
+    fs::File,
+    io::{self, BufReader},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{
+    material::Material,
+    triangle::{MeshError, TriangleMesh},
+    vec3::Point3,
+};
+
+#[derive(Debug)]
+pub enum StlError {
+    Io(PathBuf, io::Error),
+    Mesh(PathBuf, MeshError),
+    Empty(PathBuf),
+}
+
+impl Display for StlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StlError::Io(path, err) => write!(f, "{}: {}", path.display(), err),
+            StlError::Mesh(path, err) => {
+                write!(f, "{}: {}", path.display(), err)
+            }
+            StlError::Empty(path) => {
+                write!(f, "{}: no triangles", path.display())
+            }
+        }
+    }
+}
+
+impl Error for StlError {}
+
+// Loads an ASCII or binary STL file. STL only stores face normals, so the
+// mesh is flat shaded.
+pub fn load(
+    path: &Path,
+    material: Arc<dyn Material>,
+) -> Result<TriangleMesh, StlError> {
+    let io_error = |err| StlError::Io(path.to_path_buf(), err);
+
+    let mut reader = BufReader::new(File::open(path).map_err(io_error)?);
+    let stl = stl_io::read_stl(&mut reader).map_err(io_error)?;
+    if stl.faces.is_empty() {
+        return Err(StlError::Empty(path.to_path_buf()));
+    }
+
+    let positions = stl
+        .vertices
+        .iter()
+        .map(|v| Point3::new(v[0] as f64, v[1] as f64, v[2] as f64))
+        .collect();
+    let indices = stl.faces.iter().map(|face| face.vertices).collect();
+
+    TriangleMesh::new(positions, None, None, None, indices, material)
+        .map_err(|err| StlError::Mesh(path.to_path_buf(), err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        hit::Hittable,
+        material::Lambertian,
+        ray::Ray,
+        testing::{error, fixture},
+        vec3::{Color, Vec3},
+    };
+
+    fn gray() -> Arc<dyn Material> {
+        Arc::new(Lambertian::from_solid_color(Color::from_float(0.5)))
+    }
+
+    #[test]
+    fn loads_ascii_and_binary_files() {
+        for name in ["triangle_ascii.stl", "triangle_binary.stl"] {
+            let mesh = load(&fixture(name), gray()).unwrap();
+            let hit = |x: f64, y: f64| {
+                let ray =
+                    Ray::new(Point3::new(x, y, 1.0), Vec3::from_z(-1.0), 0.0);
+                mesh.hit(&ray, 0.001, f64::INFINITY)
+            };
+            assert!(hit(0.25, 0.25).is_some(), "{}", name);
+            assert!(hit(0.75, 0.75).is_none(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn reports_files_without_triangles() {
+        let path = fixture("empty.stl");
+        assert_eq!(
+            error(load(&path, gray())),
+            format!("{}: no triangles", path.display())
+        );
+    }
+}
@@ -0,0 +1,36 @@
+// Helpers shared by the unit tests
+
+use std::{
+    f64::consts::PI,
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use crate::pdf::{Pdf, SpherePdf};
+
+// A file of tests/fixtures
+pub fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name)
+}
+
+// The message of what a loader failed with
+pub fn error<T, E: Display>(result: Result<T, E>) -> String {
+    match result {
+        Ok(_) => panic!("loaded without an error"),
+        Err(err) => err.to_string(),
+    }
+}
+
+// Monte Carlo estimate of the integral of a density over the sphere, should
+// be 1
+pub fn integral(pdf: &dyn Pdf) -> f64 {
+    let n = 400_000;
+    (0..n)
+        .map(|_| pdf.value(&SpherePdf.generate()))
+        .sum::<f64>()
+        * 4.0
+        * PI
+        / n as f64
+}
@@ -1,15 +1,20 @@
-use image::{
-    io::Reader as ImageReader, GenericImageView, ImageBuffer, Rgb, RgbImage,
-};
-use std::sync::Arc;
+use image::{ImageResult, RgbImage};
+use std::{path::Path, sync::Arc};
 
 use crate::{
+    hit::HitRecord,
     perlin::Perlin,
     vec3::{Color, Vec3},
 };
 
 pub trait Texture: Send + Sync {
     fn value(&self, u: f64, v: f64, p: &Vec3) -> Color;
+
+    // The value at a hit, for textures that need more of it than the uv and
+    // the point. Materials look their textures up with this one
+    fn value_at(&self, rec: &HitRecord) -> Color {
+        self.value(rec.u, rec.v, &rec.p)
+    }
 }
 
 pub struct SolidColor {
@@ -20,12 +25,6 @@ impl SolidColor {
     pub fn new(color: Color) -> Self {
         SolidColor { color }
     }
-
-    fn new_from_rgb(red: f64, green: f64, blue: f64) -> Self {
-        SolidColor {
-            color: Color::new(red, green, blue),
-        }
-    }
 }
 
 impl Texture for SolidColor {
@@ -45,10 +44,7 @@ impl CheckerTexture {
     }
 
     pub fn from_colors(c1: Color, c2: Color) -> Self {
-        CheckerTexture {
-            odd: Arc::new(SolidColor::new(c1)),
-            even: Arc::new(SolidColor::new(c2)),
-        }
+        Self::new(Arc::new(SolidColor::new(c1)), Arc::new(SolidColor::new(c2)))
     }
 }
 
@@ -86,42 +82,53 @@ impl Texture for NoiseTexture {
     }
 }
 
+// The colors of a mesh's vertices, blended across its triangles. White off
+// meshes without colors
+pub struct VertexColorTexture;
+
+impl Texture for VertexColorTexture {
+    fn value(&self, _u: f64, _v: f64, _p: &Vec3) -> Color {
+        Color::ONE
+    }
+
+    fn value_at(&self, rec: &HitRecord) -> Color {
+        rec.vertex_color.unwrap_or(Color::ONE)
+    }
+}
+
 pub struct ImageTexture {
     data: Option<RgbImage>,
     width: u32,
     height: u32,
-    bytes_per_scanline: u32,
 }
 
 impl ImageTexture {
-    const BYTES_PER_PIXEL: u32 = 3;
+    pub fn new(data: RgbImage) -> Self {
+        let (width, height) = data.dimensions();
 
-    pub fn from_filename(filename: String) -> ImageTexture {
-        match image::open(filename) {
-            Ok(img) => {
-                let data = img.to_rgb8();
-                let (width, height) = data.dimensions();
-                let bytes_per_scanline = Self::BYTES_PER_PIXEL * width;
-
-                ImageTexture {
-                    data: Some(data),
-                    width,
-                    height,
-                    bytes_per_scanline,
-                }
-            }
-            _ => ImageTexture {
-                data: None,
-                width: 0,
-                height: 0,
-                bytes_per_scanline: 0,
-            },
+        ImageTexture {
+            data: Some(data),
+            width,
+            height,
         }
     }
+
+    pub fn open(path: &Path) -> ImageResult<ImageTexture> {
+        Ok(Self::new(image::open(path)?.to_rgb8()))
+    }
+
+    // Missing or broken images render as cyan
+    pub fn from_filename(filename: String) -> ImageTexture {
+        Self::open(Path::new(&filename)).unwrap_or(ImageTexture {
+            data: None,
+            width: 0,
+            height: 0,
+        })
+    }
 }
 
 impl Texture for ImageTexture {
-    fn value(&self, u: f64, v: f64, p: &Vec3) -> Color {
+    fn value(&self, u: f64, v: f64, _p: &Vec3) -> Color {
         match &self.data {
             Some(img) => {
                 let u = u.clamp(0.0, 1.0);
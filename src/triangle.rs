@@ -0,0 +1,385 @@
+use std::{
+    error::Error,
+    fmt::{self, Display},
+    sync::Arc,
+};
+
+use crate::{
+    aabb::AABB,
+    bvh::Bvh,
+    hit::{HitRecord, Hittable, World},
+    material::Material,
+    ray::Ray,
+    vec3::{Color, Point3, Vec3},
+};
+
+pub struct Triangle {
+    vertices: [Point3; 3],
+    material: Arc<dyn Material>,
+}
+
+impl Triangle {
+    pub fn new(
+        v0: Point3,
+        v1: Point3,
+        v2: Point3,
+        material: Arc<dyn Material>,
+    ) -> Self {
+        Self {
+            vertices: [v0, v1, v2],
+            material,
+        }
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let (t, b1, b2) = intersect(ray, &self.vertices, t_min, t_max)?;
+
+        let [v0, v1, v2] = self.vertices;
+        let mut rec = HitRecord {
+            p: ray.at(t),
+            normal: Vec3::ZERO,
+            mat: self.material.clone(),
+            t,
+            u: b1,
+            v: b2,
+            front_face: false,
+            outside_index_of_refraction: 1.0,
+            vertex_color: None,
+        };
+        rec.set_face_normal(ray, (v1 - v0).cross(v2 - v0).normalized());
+
+        Some(rec)
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<AABB> {
+        Some(bounding_box(&self.vertices))
+    }
+}
+
+// Möller–Trumbore, returns the ray parameter and the barycentric coordinates
+// of v1 and v2
+fn intersect(
+    ray: &Ray,
+    vertices: &[Point3; 3],
+    t_min: f64,
+    t_max: f64,
+) -> Option<(f64, f64, f64)> {
+    let [v0, v1, v2] = *vertices;
+    let edge1 = v1 - v0;
+    let edge2 = v2 - v0;
+
+    let pvec = ray.direction().cross(edge2);
+    let det = edge1.dot(pvec);
+    // only a ray in the plane of the triangle, or a degenerate triangle, has
+    // no solution; tiny determinants of small or far away triangles are fine
+    if det == 0.0 || !det.is_finite() {
+        return None;
+    }
+    let inv_det = 1.0 / det;
+
+    let tvec = ray.origin() - v0;
+    let b1 = tvec.dot(pvec) * inv_det;
+    if !(0.0..=1.0).contains(&b1) {
+        return None;
+    }
+
+    let qvec = tvec.cross(edge1);
+    let b2 = ray.direction().dot(qvec) * inv_det;
+    if b2 < 0.0 || b1 + b2 > 1.0 {
+        return None;
+    }
+
+    let t = edge2.dot(qvec) * inv_det;
+    if t < t_min || t > t_max {
+        return None;
+    }
+
+    Some((t, b1, b2))
+}
+
+fn bounding_box(vertices: &[Point3; 3]) -> AABB {
+    let mut min = Point3::from_float(f64::INFINITY);
+    let mut max = Point3::from_float(f64::NEG_INFINITY);
+
+    for v in vertices {
+        for c in 0..3 {
+            min[c] = min[c].min(v[c]);
+            max[c] = max[c].max(v[c]);
+        }
+    }
+
+    // axis aligned triangles would get a flat box, pad it like the rects do
+    for c in 0..3 {
+        if max[c] - min[c] < 0.0001 {
+            min[c] -= 0.0001;
+            max[c] += 0.0001;
+        }
+    }
+
+    AABB::new(min, max)
+}
+
+struct MeshData {
+    positions: Vec<Point3>,
+    normals: Option<Vec<Vec3>>,
+    uvs: Option<Vec<(f64, f64)>>,
+    colors: Option<Vec<Color>>,
+    indices: Vec<[usize; 3]>,
+    material: Arc<dyn Material>,
+}
+
+// Triangles sharing the vertex buffers of a mesh
+struct MeshTriangle {
+    mesh: Arc<MeshData>,
+    index: usize,
+}
+
+impl MeshTriangle {
+    fn vertices(&self) -> [Point3; 3] {
+        let [i0, i1, i2] = self.mesh.indices[self.index];
+        let positions = &self.mesh.positions;
+        [positions[i0], positions[i1], positions[i2]]
+    }
+}
+
+impl Hittable for MeshTriangle {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let vertices = self.vertices();
+        let (t, b1, b2) = intersect(ray, &vertices, t_min, t_max)?;
+        let b0 = 1.0 - b1 - b2;
+
+        let [i0, i1, i2] = self.mesh.indices[self.index];
+        let (u, v) = match &self.mesh.uvs {
+            Some(uvs) => (
+                b0 * uvs[i0].0 + b1 * uvs[i1].0 + b2 * uvs[i2].0,
+                b0 * uvs[i0].1 + b1 * uvs[i1].1 + b2 * uvs[i2].1,
+            ),
+            None => (b1, b2),
+        };
+
+        let mut rec = HitRecord {
+            p: ray.at(t),
+            normal: Vec3::ZERO,
+            mat: self.mesh.material.clone(),
+            t,
+            u,
+            v,
+            front_face: false,
+            outside_index_of_refraction: 1.0,
+            vertex_color: self.mesh.colors.as_ref().map(|colors| {
+                b0 * colors[i0] + b1 * colors[i1] + b2 * colors[i2]
+            }),
+        };
+
+        let [v0, v1, v2] = vertices;
+        rec.set_face_normal(ray, (v1 - v0).cross(v2 - v0).normalized());
+
+        // the side is decided by the geometric normal, shading uses the
+        // interpolated one
+        if let Some(normals) = &self.mesh.normals {
+            let shading_normal =
+                b0 * normals[i0] + b1 * normals[i1] + b2 * normals[i2];
+            if !shading_normal.near_zero() {
+                let shading_normal = shading_normal.normalized();
+                rec.normal = if rec.front_face {
+                    shading_normal
+                } else {
+                    -shading_normal
+                };
+            }
+        }
+
+        Some(rec)
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<AABB> {
+        Some(bounding_box(&self.vertices()))
+    }
+}
+
+#[derive(Debug)]
+pub enum MeshError {
+    Empty,
+    IndexOutOfRange(usize, usize),
+    // attribute, expected count
+    VertexCount(&'static str, usize),
+}
+
+impl Display for MeshError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MeshError::Empty => write!(f, "a mesh needs at least one triangle"),
+            MeshError::IndexOutOfRange(index, count) => write!(
+                f,
+                "index {} out of range for {} positions",
+                index, count
+            ),
+            MeshError::VertexCount(attribute, count) => {
+                write!(f, "expected {} {}", count, attribute)
+            }
+        }
+    }
+}
+
+impl Error for MeshError {}
+
+pub struct TriangleMesh {
+    bvh: Bvh,
+}
+
+impl TriangleMesh {
+    // `normals`, `uvs` and `colors`, when given, hold one entry per position.
+    // The colors reach the material through `HitRecord::vertex_color`, see
+    // `VertexColorTexture`
+    pub fn new(
+        positions: Vec<Point3>,
+        normals: Option<Vec<Vec3>>,
+        uvs: Option<Vec<(f64, f64)>>,
+        colors: Option<Vec<Color>>,
+        indices: Vec<[usize; 3]>,
+        material: Arc<dyn Material>,
+    ) -> Result<Self, MeshError> {
+        if indices.is_empty() {
+            return Err(MeshError::Empty);
+        }
+        if let Some(&i) =
+            indices.iter().flatten().find(|&&i| i >= positions.len())
+        {
+            return Err(MeshError::IndexOutOfRange(i, positions.len()));
+        }
+        if normals.as_ref().is_some_and(|n| n.len() != positions.len()) {
+            return Err(MeshError::VertexCount("normals", positions.len()));
+        }
+        if uvs.as_ref().is_some_and(|uv| uv.len() != positions.len()) {
+            return Err(MeshError::VertexCount("uvs", positions.len()));
+        }
+        if colors.as_ref().is_some_and(|c| c.len() != positions.len()) {
+            return Err(MeshError::VertexCount("colors", positions.len()));
+        }
+
+        let triangle_count = indices.len();
+        let mesh = Arc::new(MeshData {
+            positions,
+            normals,
+            uvs,
+            colors,
+            indices,
+            material,
+        });
+
+        let triangles: World = (0..triangle_count)
+            .map(|index| {
+                Box::new(MeshTriangle {
+                    mesh: mesh.clone(),
+                    index,
+                }) as Box<dyn Hittable>
+            })
+            .collect();
+
+        Ok(Self {
+            bvh: Bvh::new(triangles, 0.0, 1.0),
+        })
+    }
+}
+
+impl Hittable for TriangleMesh {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        self.bvh.hit(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<AABB> {
+        self.bvh.bounding_box(time0, time1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+
+    fn gray() -> Arc<dyn Material> {
+        Arc::new(Lambertian::from_solid_color(Color::from_float(0.5)))
+    }
+
+    #[test]
+    fn hits_tiny_triangles() {
+        // the determinant is around 1e-18 here
+        let scale = 1e-9;
+        let triangle = Triangle::new(
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(scale, 0.0, 0.0),
+            Point3::new(0.0, scale, 0.0),
+            gray(),
+        );
+        let ray = Ray::new(
+            Point3::new(0.25 * scale, 0.25 * scale, -1.0),
+            Vec3::from_z(1.0),
+            0.0,
+        );
+        let rec = triangle.hit(&ray, 0.0, f64::INFINITY).unwrap();
+        assert!((rec.t - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn blends_vertex_colors_and_keeps_uvs() {
+        let mesh = TriangleMesh::new(
+            vec![Point3::ZERO, Vec3::from_x(1.0), Vec3::from_y(1.0)],
+            None,
+            Some(vec![(0.0, 0.0), (2.0, 0.0), (0.0, 2.0)]),
+            Some(vec![
+                Color::new(1.0, 0.0, 0.0),
+                Color::new(0.0, 1.0, 0.0),
+                Color::new(0.0, 0.0, 1.0),
+            ]),
+            vec![[0, 1, 2]],
+            gray(),
+        )
+        .unwrap();
+        let ray =
+            Ray::new(Point3::new(0.5, 0.25, -1.0), Vec3::from_z(1.0), 0.0);
+        let rec = mesh.hit(&ray, 0.0, f64::INFINITY).unwrap();
+        assert!((rec.u - 1.0).abs() < 1e-12 && (rec.v - 0.5).abs() < 1e-12);
+        let color = rec.vertex_color.unwrap();
+        assert!((color - Color::new(0.25, 0.5, 0.25)).length() < 1e-12);
+    }
+
+    #[test]
+    fn rejects_bad_meshes() {
+        let positions = vec![Point3::ZERO, Point3::ONE, Vec3::from_x(1.0)];
+        assert!(matches!(
+            TriangleMesh::new(
+                positions.clone(),
+                None,
+                None,
+                None,
+                vec![],
+                gray()
+            ),
+            Err(MeshError::Empty)
+        ));
+        assert!(matches!(
+            TriangleMesh::new(
+                positions.clone(),
+                None,
+                None,
+                None,
+                vec![[0, 1, 3]],
+                gray()
+            ),
+            Err(MeshError::IndexOutOfRange(3, 3))
+        ));
+        assert!(matches!(
+            TriangleMesh::new(
+                positions,
+                Some(vec![Vec3::ONE]),
+                None,
+                None,
+                vec![[0, 1, 2]],
+                gray()
+            ),
+            Err(MeshError::VertexCount("normals", 3))
+        ));
+    }
+}
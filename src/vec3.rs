@@ -80,21 +80,9 @@ impl Vec3 {
         .abs()
     }
 
-    pub fn format_color(self, samples_per_pixel: u64) -> String {
-        let ir = (256.0
-            * (self[0] / (samples_per_pixel as f64))
-                .sqrt()
-                .clamp(0.0, 0.999)) as u64;
-        let ig = (256.0
-            * (self[1] / (samples_per_pixel as f64))
-                .sqrt()
-                .clamp(0.0, 0.999)) as u64;
-        let ib = (256.0
-            * (self[2] / (samples_per_pixel as f64))
-                .sqrt()
-                .clamp(0.0, 0.999)) as u64;
-
-        format!("{} {} {}", ir, ig, ib)
+    pub fn to_rgb8(self) -> [u8; 3] {
+        let encode = |c: f64| (256.0 * c.sqrt().clamp(0.0, 0.999)) as u8;
+        [encode(self[0]), encode(self[1]), encode(self[2])]
     }
 
     pub fn random(r: Range<f64>) -> Vec3 {
@@ -143,6 +131,18 @@ impl Vec3 {
         }
     }
 
+    // Two unit vectors completing `self`, which must be a unit vector, to an
+    // orthonormal basis
+    pub fn orthonormal_basis(self) -> (Vec3, Vec3) {
+        let a = if self.x().abs() > 0.9 {
+            Vec3::from_y(1.0)
+        } else {
+            Vec3::from_x(1.0)
+        };
+        let t = self.cross(a).normalized();
+        (t, self.cross(t))
+    }
+
     pub fn near_zero(self) -> bool {
         const EPS: f64 = 1.0e-8;
         self[0].abs() < EPS && self[1].abs() < EPS && self[2].abs() < EPS
@@ -283,6 +283,12 @@ impl ops::DivAssign<f64> for Vec3 {
     }
 }
 
+impl From<[f64; 3]> for Vec3 {
+    fn from(e: [f64; 3]) -> Self {
+        Vec3 { e }
+    }
+}
+
 impl Display for Vec3 {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         writeln!(f, "({}, {}, {})", self[0], self[1], self[2])
@@ -0,0 +1,156 @@
+use std::sync::Arc;
+
+use raytracer_rs::{
+    bvh::Bvh,
+    material::{Dielectric, DiffuseLight, Lambertian, Metal},
+    render, render_with_stats,
+    sphere::Sphere,
+    texture::SolidColor,
+    Color, Integrator, Point3, RenderSettings, Scene,
+};
+
+fn scene() -> Scene {
+    let mut scene = Scene {
+        background: Color::ZERO,
+        lookfrom: Point3::from_z(5.0),
+        lookat: Point3::ZERO,
+        aperture: 0.0,
+        samples_per_pixel: 4,
+        max_depth: 4,
+        ..Scene::default()
+    };
+    scene.set_aspect_ratio(2.0);
+    scene.set_image_width(16);
+
+    let light = Arc::new(DiffuseLight::new(Arc::new(SolidColor::new(
+        Color::from_float(4.0),
+    ))));
+    let gray = Arc::new(Lambertian::new(Arc::new(SolidColor::new(
+        Color::from_float(0.5),
+    ))));
+    scene
+        .world
+        .push(Box::new(Sphere::new(Point3::ZERO, 1.0, light)));
+    scene.world.push(Box::new(Sphere::new(
+        Point3::from_y(-101.0),
+        100.0,
+        gray,
+    )));
+
+    scene
+}
+
+#[test]
+fn renders_the_scene_size() {
+    let framebuffer = render(&scene(), &RenderSettings::default());
+    assert_eq!((framebuffer.width(), framebuffer.height()), (16, 8));
+}
+
+#[test]
+fn sees_the_light_in_the_middle() {
+    let framebuffer = render(&scene(), &RenderSettings::default());
+
+    // the light fills the centre of the frame, the background is black
+    assert_eq!(framebuffer.get(8, 4), Color::from_float(4.0));
+    assert_eq!(framebuffer.get(0, 0), Color::ZERO);
+}
+
+#[test]
+fn counts_bvh_work_only_when_asked() {
+    let mut scene = scene();
+    let objects = std::mem::take(&mut scene.world);
+    scene.world.push(Box::new(Bvh::new(objects, 0.0, 1.0)));
+
+    let (_, stats) = render_with_stats(&scene, &RenderSettings::default());
+    assert!(stats.is_none());
+
+    let settings = RenderSettings {
+        bvh_stats: true,
+        ..RenderSettings::default()
+    };
+    let stats = render_with_stats(&scene, &settings).1.unwrap();
+    // at least a camera ray per sample, each visiting the root
+    assert!(stats.rays >= 16 * 8 * 4);
+    assert!(stats.nodes_visited >= stats.rays);
+}
+
+#[test]
+fn absorbing_glass_tints_the_light_behind() {
+    let mut scene = scene();
+    // an index of 1 doesn't bend or reflect, only absorbs
+    let glass = Arc::new(
+        Dielectric::new(1.0).with_absorption(Color::new(0.0, 0.5, 1.0)),
+    );
+    scene
+        .world
+        .push(Box::new(Sphere::new(Point3::from_z(2.5), 1.0, glass)));
+    let framebuffer = render(&scene, &RenderSettings::default());
+
+    // about 2 across the middle of the glass
+    let color = framebuffer.get(8, 4);
+    assert!((color.x() - 4.0).abs() < 1e-6);
+    assert!((color.y() - 4.0 * (-1.0_f64).exp()).abs() < 0.1);
+    assert!((color.z() - 4.0 * (-2.0_f64).exp()).abs() < 0.1);
+}
+
+#[test]
+fn light_sampling_cuts_the_noise_of_fuzzy_metal() {
+    // a small light seen in a slightly fuzzy metal floor, the reflection is
+    // in the middle of the frame
+    let mut scene = Scene {
+        background: Color::ZERO,
+        lookfrom: Point3::new(0.0, 1.0, 3.0),
+        lookat: Point3::ZERO,
+        vfov: 10.0,
+        aperture: 0.0,
+        samples_per_pixel: 4,
+        max_depth: 4,
+        ..Scene::default()
+    };
+    scene.set_aspect_ratio(1.0);
+    scene.set_image_width(8);
+    let light = Arc::new(DiffuseLight::new(Arc::new(SolidColor::new(
+        Color::from_float(50.0),
+    ))));
+    let metal = Arc::new(Metal::new(Color::from_float(0.9), 0.2));
+    scene.world.push(Box::new(Sphere::new(
+        Point3::new(0.0, 1.0, -3.0),
+        0.15,
+        light.clone(),
+    )));
+    scene.lights.push(Box::new(Sphere::new(
+        Point3::new(0.0, 1.0, -3.0),
+        0.15,
+        light,
+    )));
+    scene.world.push(Box::new(Sphere::new(
+        Point3::from_y(-1000.0),
+        1000.0,
+        metal,
+    )));
+
+    // per pixel variance between renders, summed over the image
+    let variance = |integrator| {
+        let settings = RenderSettings {
+            integrator,
+            ..RenderSettings::default()
+        };
+        let renders: Vec<_> =
+            (0..16).map(|_| render(&scene, &settings)).collect();
+        let mut total = 0.0;
+        for j in 0..8 {
+            for i in 0..8 {
+                let values: Vec<f64> =
+                    renders.iter().map(|f| f.get(i, j).y()).collect();
+                let mean = values.iter().sum::<f64>() / values.len() as f64;
+                total += values.iter().map(|v| (v - mean).powi(2)).sum::<f64>()
+                    / (values.len() - 1) as f64;
+            }
+        }
+        total
+    };
+
+    let mis = variance(Integrator::Mis);
+    let bsdf = variance(Integrator::Bsdf);
+    assert!(mis < 0.5 * bsdf, "mis {} bsdf {}", mis, bsdf);
+}